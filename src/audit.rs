@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub event: AuditEvent,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AuditEvent {
+    QuestionAdded(Uuid),
+    QuestionUpdated(Uuid),
+    QuestionPosted(Uuid),
+    QuestionPruned(Uuid),
+    QuestionDeleted(Uuid),
+    SourceFetchFailed(String),
+    PostingPaused,
+    PostingResumed,
+}
+
+impl AuditEntry {
+    pub fn new(event: AuditEvent) -> Self {
+        Self::at(event, Utc::now())
+    }
+
+    /// An entry for something that happened at `timestamp` rather than now
+    pub fn at(event: AuditEvent, timestamp: DateTime<Utc>) -> Self {
+        Self { timestamp, event }
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.event
+        )
+    }
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditEvent::QuestionAdded(id) => write!(f, "Question added {}", id),
+            AuditEvent::QuestionUpdated(id) => write!(f, "Question updated {}", id),
+            AuditEvent::QuestionPosted(id) => write!(f, "Question posted {}", id),
+            AuditEvent::QuestionPruned(id) => write!(f, "Question pruned {}", id),
+            AuditEvent::QuestionDeleted(id) => write!(f, "Question deleted {}", id),
+            AuditEvent::SourceFetchFailed(error) => write!(f, "Source fetch failed: {}", error),
+            AuditEvent::PostingPaused => write!(f, "Posting paused"),
+            AuditEvent::PostingResumed => write!(f, "Posting resumed"),
+        }
+    }
+}
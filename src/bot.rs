@@ -1,25 +1,126 @@
-use chrono::{NaiveTime, Timelike};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use cron::Schedule;
+use futures::future::join_all;
 use isahc::prelude::*;
-use rand::prelude::SliceRandom;
+use rand::{prelude::SliceRandom, Rng};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serenity::model::channel::Embed;
-use std::fmt;
+use serde_json::{json, Map, Value};
+use serenity::builder::ExecuteWebhook;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+};
 use tokio::{
-    fs::OpenOptions,
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::{mpsc, Semaphore},
     time,
 };
-use tracing::info;
+use tracing::{debug, error, info, warn};
+use unicode_segmentation::UnicodeSegmentation;
 use uuid::Uuid;
 
+use crate::{
+    audit::{AuditEntry, AuditEvent},
+    control::PostRequest,
+    discord::{DiscordApi, PostedMessage, SerenityApi, Unavailable},
+    embed::{fit_embed, EmbedTemplate, RenderContext, MAX_EMBED_FIELDS},
+    fetcher::{HttpPolicy, IsahcFetcher, SourceFetcher, TimedOut},
+    source::{Entry, PlainOptions, SourceFormat},
+    storage::{JsonFileStorage, State, StateLock, Storage},
+};
+
+/// Fire times looked at when searching for the next one that isn't excluded
+const MAX_SCHEDULE_LOOKAHEAD: usize = 1000;
+
+/// Time between two ticks, every minute gets one
+const TICK: time::Duration = time::Duration::from_secs(60);
+/// Longest source poll jitter, leaving the rest of the tick for its work. A tick running into
+/// the next one would make both see the same minute and skip the other one.
+const MAX_POLL_JITTER: time::Duration = time::Duration::from_secs(30);
+
+/// Largest source response read unless configured otherwise
+pub const DEFAULT_MAX_SOURCE_SIZE: u64 = 1_048_576;
+
 type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
 
-#[derive(Serialize, Deserialize)]
 pub struct Bot {
-    url: String,
+    urls: Vec<String>,
     hook: Webhook,
+    /// Gets the announcements and operational warnings instead of the question channel
+    pub notification_hook: Option<Webhook>,
     post_at: NaiveTime,
-    pub questions: Vec<Question>,
+    /// Posts whenever this fires instead of daily at `post_at`
+    pub schedule: Option<Schedule>,
+    /// Timezone `schedule` is evaluated in
+    pub timezone: Tz,
+    pub source_format: SourceFormat,
+    pub plain_options: PlainOptions,
+    /// Maximum random delay before the first fetch, to stagger restarted instances
+    pub startup_jitter: time::Duration,
+    /// Maximum random delay before every fetch, to spread out instances polling the same source.
+    /// Capped at `MAX_POLL_JITTER`.
+    pub source_poll_jitter: time::Duration,
+    pub exclude_dates: Vec<NaiveDate>,
+    pub exclude_date_ranges: Vec<DateRange>,
+    /// Minimum time between two posts from the same category
+    pub category_cooldowns: Vec<CategoryCooldown>,
+    /// Log why each question was picked
+    pub explain: bool,
+    pub selection_mode: SelectionMode,
+    /// Post a message once the first tick has loaded the questions
+    pub startup_announcement: bool,
+    startup_announced: bool,
+    pub template: EmbedTemplate,
+    pub dedup: DedupOptions,
+    /// Fetch all sources at the same time instead of one after the other
+    pub concurrent_sources: bool,
+    pub max_concurrent_fetches: usize,
+    pub unsplash_api_key: Option<String>,
+    /// Illustrate questions with an Unsplash photo matching their category
+    pub unsplash_query_from_category: bool,
+    /// Shell command run before every post, whose output is posted ahead of the usual pick
+    pub pre_post_command: Option<String>,
+    pub pre_post_command_timeout: time::Duration,
+    /// Number of audit log entries to keep
+    pub audit_log_size: usize,
+    /// Questions read from a single source at most
+    pub max_source_lines: usize,
+    /// Longer questions in a source are skipped
+    pub max_source_line_length: usize,
+    /// Skip fetching the sources while at least this many questions are unanswered, zero to always fetch
+    pub min_unanswered_before_load: usize,
+    /// Questions posted within this many days aren't asked again, even pinned ones
+    pub uniqueness_window_days: Option<u32>,
+    /// Ask for more questions once fewer than this many are unanswered, zero to never ask
+    pub low_watermark: usize,
+    /// Number of answered questions to keep, zero to keep all of them
+    pub answered_questions_keep: usize,
+    /// How many questions are posted at every scheduled post
+    pub questions_per_post: usize,
+    /// How long after posting a question its reactions are counted
+    pub fetch_reactions_after: Duration,
+    /// Gets the most reacted questions of the week on Mondays, after the post time
+    pub summary_hook: Option<Webhook>,
+    /// Day of the month a recap of the month's questions is posted on, after the post time
+    pub monthly_recap_day: Option<u32>,
+    /// How long before the question a teaser with its category is posted, zero to disable
+    pub intro_offset: Duration,
+    pub storage: Box<dyn Storage + Send + Sync>,
+    /// Requests from the control endpoint to post right away
+    pub manual_posts: Option<mpsc::Receiver<PostRequest>>,
+    /// Shared with the tasks spawned to fetch sources concurrently
+    pub fetcher: Arc<dyn SourceFetcher>,
+    /// Sources aren't fetched again this long after they last were, zero to fetch every tick
+    pub cache_source_ttl: Duration,
+    last_fetch_time: Option<DateTime<Utc>>,
+    /// Question already marked posted that Discord turned away, sent again on the next tick
+    unsent: Option<Uuid>,
+    pub state: State,
+    /// Normalized question text to id, for finding duplicates without comparing every question
+    norm_index: HashMap<String, Uuid>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,223 +128,3905 @@ pub struct Question {
     id: Uuid,
     text: String,
     answered: bool,
+    author: Option<String>,
+    category: Option<String>,
+    #[serde(default)]
+    poll_options: Vec<String>,
+    answered_at: Option<DateTime<Utc>>,
+    /// Pinned questions stay eligible even after they have been posted
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default = "Utc::now")]
+    added_at: DateTime<Utc>,
+    embed_image_url: Option<String>,
+    /// Translations of the text, see `EmbedTemplate::variant`
+    #[serde(default)]
+    variants: Vec<String>,
+    /// Position in sequential selection, lower comes first
+    #[serde(default)]
+    sort_order: i64,
+    /// Posted next, ahead of the random pick, cleared once posted
+    #[serde(default)]
+    priority: bool,
+    /// Where the question was posted, for looking up its reactions
+    #[serde(default)]
+    channel_id: Option<u64>,
+    #[serde(default)]
+    message_id: Option<u64>,
+    /// Reactions on the post once `fetch_reactions_after` has passed
+    #[serde(default)]
+    reaction_count: Option<u32>,
+    /// Day the question is held back for and then posted on before any other
+    #[serde(default)]
+    scheduled_for: Option<NaiveDate>,
+    /// Extra embed fields like hints or references, as name, value and whether it's inline
+    #[serde(default)]
+    custom_fields: Vec<(String, String, bool)>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How the next question is picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// Every unanswered question comes up once, in a random order, before any repeats
+    ShuffledCycle,
+    /// Any question can come up, including ones that were posted already
+    AllowRepeats,
+    /// Unanswered questions come up by their sort order, then the order they were added in
+    Sequential,
+}
+
+/// How long each check of `Bot::check_config` may take, they all run at once
+const CHECK_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// The outcome of one check of `Bot::check_config`
+#[derive(Debug, Serialize)]
+pub struct ConfigCheck {
+    pub check: &'static str,
+    pub target: String,
+    /// None when the check passed
+    pub error: Option<String>,
+}
+
+impl ConfigCheck {
+    async fn run(
+        check: &'static str,
+        target: String,
+        call: impl std::future::Future<Output = Result<(), Err>>,
+    ) -> Self {
+        let error = match time::timeout(CHECK_TIMEOUT, call).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("timed out after {:?}", CHECK_TIMEOUT)),
+        };
+
+        Self {
+            check,
+            target,
+            error,
+        }
+    }
+}
+
+/// What `validate_sources` found in a source
+#[derive(Debug)]
+pub struct SourceReport {
+    pub url: String,
+    /// Questions a load would merge, once the caps are applied
+    pub questions: usize,
+    pub problems: Vec<String>,
+}
+
+/// What a single tick changed
+#[derive(Debug)]
+pub struct TickResult {
+    /// The question an intro announced, set on the intro's tick
+    pub intro_announced: Option<Uuid>,
+    pub question_posted: Option<Uuid>,
+    pub questions_added: usize,
+    pub questions_updated: usize,
+    pub questions_removed: usize,
+}
+
+/// What the next question is chosen from
+enum Candidates {
+    /// The question a teaser already announced the category of
+    Announced(Uuid),
+    /// A question scheduled for that date, or missed on it
+    Scheduled(Uuid, NaiveDate),
+    Prioritized(Uuid),
+    /// Left to the selection mode, among the questions outside the uniqueness window
+    Eligible(HashSet<Uuid>),
+}
+
+/// Questions a batch of source entries was merged into
+#[derive(Debug, Default)]
+struct Merge {
+    listed: Vec<Uuid>,
+    added: usize,
+    updated: usize,
+}
+
+/// How source lines are matched against stored questions
+#[derive(Debug)]
+pub struct DedupOptions {
+    /// Only merge source lines that are identical to a stored question
+    pub exact: bool,
+    /// Merge when the edit distance is below this
+    pub threshold: usize,
+    /// Merge when the normalized similarity is at least this
+    pub similarity_threshold: f64,
+    /// Only merge text that is identical after normalization, skipping the fuzzy comparison
+    pub strict_only: bool,
+    /// How near matches are scored
+    pub algorithm: DeduplicationAlgorithm,
+}
+
+/// How similar a source line and a stored question are scored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeduplicationAlgorithm {
+    Levenshtein,
+    /// Levenshtein that counts swapping two neighbouring characters as one edit
+    DamerauLevenshtein,
+    /// Weighs matching starts more, only the similarity threshold applies
+    JaroWinkler,
+    /// Text has to be identical after normalization
+    Exact,
+}
+
+impl FromStr for DeduplicationAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "levenshtein" => Ok(Self::Levenshtein),
+            "damerau_levenshtein" => Ok(Self::DamerauLevenshtein),
+            "jaro_winkler" => Ok(Self::JaroWinkler),
+            "exact" => Ok(Self::Exact),
+            _ => Err(format!("Unknown dedup algorithm: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Webhook {
     id: u64,
     token: String,
+    pub footer_timezone: Tz,
+    /// Start a thread on every posted question, this needs a bot token
+    pub create_thread: bool,
+    pub bot_token: Option<String>,
+    /// How long a call to Discord may take before it fails, and how often it's retried
+    pub http_policy: HttpPolicy,
+    /// The webhook posts to a forum channel, where every message starts a post of its own
+    pub forum: bool,
+    /// Ids of the forum tags applied to every post
+    pub forum_tags: Vec<u64>,
+    /// How long polls stay open
+    pub poll_duration_hours: u16,
+    /// Message of this webhook that questions replace instead of being posted anew, so
+    /// it can stay pinned. Polls are still posted as messages of their own.
+    pub edit_pinned: Option<u64>,
+    /// Least time between two messages, so posting several in a row stays under Discord's
+    /// limit of five per two seconds. A 429 is still retried by serenity after its Retry-After.
+    pub rate_limit_delay: time::Duration,
+    /// When the last message went out, shared by the clones of a webhook
+    last_sent: Arc<std::sync::Mutex<Option<time::Instant>>>,
+    pub api: Arc<dyn DiscordApi>,
 }
 
 impl Bot {
-    pub fn new(url: String, hook: Webhook, post_at: NaiveTime) -> Self {
+    pub fn new(codes: Vec<String>, hook: Webhook, post_at: NaiveTime) -> Self {
+        // Anything that isn't a full URL is treated as a pastebin code
+        let urls = codes
+            .into_iter()
+            .map(|code| {
+                if code.starts_with("http://")
+                    || code.starts_with("https://")
+                    || code.starts_with("s3://")
+                {
+                    code
+                } else {
+                    format!("https://pastebin.com/raw/{}", code)
+                }
+            })
+            .collect();
+
         Self {
-            questions: vec![],
-            url: format!("https://pastebin.com/raw/{}", url),
+            state: State::default(),
+            urls,
             hook,
+            notification_hook: None,
             post_at,
+            schedule: None,
+            timezone: Tz::UTC,
+            source_format: SourceFormat::Plain,
+            plain_options: PlainOptions::default(),
+            startup_jitter: time::Duration::ZERO,
+            source_poll_jitter: time::Duration::ZERO,
+            exclude_dates: vec![],
+            exclude_date_ranges: vec![],
+            category_cooldowns: vec![],
+            explain: false,
+            selection_mode: SelectionMode::ShuffledCycle,
+            startup_announcement: false,
+            startup_announced: false,
+            template: EmbedTemplate::default(),
+            dedup: DedupOptions::default(),
+            concurrent_sources: false,
+            max_concurrent_fetches: 4,
+            unsplash_api_key: None,
+            unsplash_query_from_category: false,
+            pre_post_command: None,
+            pre_post_command_timeout: time::Duration::from_secs(10),
+            audit_log_size: 1000,
+            max_source_lines: 10_000,
+            max_source_line_length: 4096,
+            min_unanswered_before_load: 0,
+            uniqueness_window_days: None,
+            low_watermark: 0,
+            answered_questions_keep: 0,
+            questions_per_post: 1,
+            fetch_reactions_after: Duration::hours(24),
+            summary_hook: None,
+            monthly_recap_day: None,
+            intro_offset: Duration::zero(),
+            storage: Box::new(JsonFileStorage::new("questions.json")),
+            manual_posts: None,
+            fetcher: Arc::new(IsahcFetcher::new(
+                HttpPolicy::default(),
+                DEFAULT_MAX_SOURCE_SIZE,
+            )),
+            cache_source_ttl: Duration::zero(),
+            last_fetch_time: None,
+            unsent: None,
+            norm_index: HashMap::new(),
         }
     }
 
+    #[tracing::instrument(
+        skip(self),
+        fields(questions_count = tracing::field::Empty, sources = ?self.urls)
+    )]
     pub async fn start(&mut self) -> Result<(), Err> {
-        let mut interval = time::interval(time::Duration::from_secs(60));
+        self.log_config();
+        if !self.startup_jitter.is_zero() {
+            let delay = rand::thread_rng().gen_range(time::Duration::ZERO..=self.startup_jitter);
+            info!("Delaying startup by {:?}", delay);
+            time::sleep(delay).await;
+        }
+
+        let mut next_post = None;
+        // The interval fires right away, and a restart shouldn't count as the post minute
+        let mut first_tick = true;
+        let mut manual_posts = self.manual_posts.take();
+        let poll_jitter = self.source_poll_jitter.min(MAX_POLL_JITTER);
+        if poll_jitter < self.source_poll_jitter {
+            warn!(
+                "Source poll jitter {:?} could skip posts, using {:?}",
+                self.source_poll_jitter, poll_jitter
+            );
+        }
+        let mut interval = time::interval(TICK);
         loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = interval.tick() => {}
+                Some(done) = next_manual_post(&mut manual_posts) => {
+                    let result = self.post_now().await.map_err(|e| e.to_string());
+                    let _ = done.send(result);
+                    continue;
+                }
+            }
+            // Taken before the jitter, so a long delay can't push the tick past the post minute
+            let now = chrono::Utc::now();
+
+            if !poll_jitter.is_zero() {
+                let delay = rand::thread_rng().gen_range(time::Duration::ZERO..=poll_jitter);
+                debug!("Delaying source load by {:?}", delay);
+                time::sleep(delay).await;
+            }
 
+            // Held for the rest of the tick, so commands run meanwhile wait instead of being lost
+            let _lock = self.lock().await?;
             self.restore().await?;
-            self.load().await?;
+            let pending = self.pending_count();
+            let listed = if self.min_unanswered_before_load > 0
+                && pending >= self.min_unanswered_before_load
+            {
+                debug!(
+                    "Skipping source load: {} unanswered questions in cache",
+                    pending
+                );
+                None
+            } else {
+                match self.load().await {
+                    Ok(listed) => listed,
+                    // A slow source keeps the cached questions until the next tick
+                    Err(e) if e.is::<TimedOut>() => {
+                        warn!("Skipping source load: {}", e);
+                        self.record(AuditEvent::SourceFetchFailed(e.to_string()));
+                        self.notify(&format!("Fetching the sources failed: {}", e))
+                            .await;
+                        None
+                    }
+                    Err(e) => {
+                        self.record(AuditEvent::SourceFetchFailed(e.to_string()));
+                        self.notify(&format!("Fetching the sources failed: {}", e))
+                            .await;
+                        self.save().await?;
+                        return Err(e);
+                    }
+                }
+            };
+            tracing::Span::current().record("questions_count", self.state.questions.len());
+            // Refilled pools can run low again
+            if self.pending_count() >= self.low_watermark {
+                self.state.low_watermark_alerted = false;
+            }
 
-            let now = chrono::Utc::now();
-            if self.questions.len() > 0
-                && now.hour() == self.post_at.hour()
-                && now.minute() == self.post_at.minute()
+            if self.startup_announcement && !self.startup_announced {
+                let text = format!(
+                    "Bot started. {} questions loaded, {} unanswered.",
+                    self.state.questions.len(),
+                    self.pending_count()
+                );
+                // Announced once either way, a failed announcement isn't worth retrying every tick
+                if let Err(e) = self
+                    .notifier()
+                    .send_announcement(&self.template, &text)
+                    .await
+                {
+                    warn!("Failed to send the startup announcement: {}", e);
+                }
+                self.startup_announced = true;
+            }
+
+            if self.intro_offset > Duration::zero() && self.schedule.is_none() && !self.state.paused
             {
-                self.answer().await?;
+                if let Err(e) = self.intro(now).await {
+                    warn!("Failed to post the intro: {}", e);
+                }
+            }
+
+            self.retry_unsent().await;
+
+            if self.is_due(now) {
+                if first_tick {
+                    info!("Skipping post: first tick after startup");
+                } else if self.state.paused {
+                    info!("Skipping post: posting is paused");
+                } else if self.is_excluded(now.date_naive()) {
+                    info!("Skipping post: excluded date {}", now.date_naive());
+                } else {
+                    self.state.last_scheduled = Some(now);
+                    match self.answer(now).await {
+                        Ok(()) => {}
+                        // The question stays posted, only sending it is retried
+                        Err(e) if e.is::<Unavailable>() => {
+                            warn!("Posting failed, retrying on the next tick: {}", e)
+                        }
+                        Err(e) if e.is::<TimedOut>() => {
+                            warn!(
+                                "Posting timed out, not sending it again in case it went out: {}",
+                                e
+                            )
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+
+            first_tick = false;
+            if let Err(e) = self.monthly_recap(now).await {
+                warn!("Failed to post the monthly recap: {}", e);
+            }
+            if self.summary_hook.is_some() {
+                self.count_reactions(now).await;
+                if let Err(e) = self.weekly_summary(now).await {
+                    warn!("Failed to post the weekly summary: {}", e);
+                }
+            }
+
+            let next = self.next_post_instant(now);
+            if next_post != Some(next) {
+                info!("Next post at {}", next);
+                next_post = Some(next);
             }
 
+            // Without a fresh load there is no telling which questions the sources still list
+            if let (true, Some(listed)) = (self.answered_questions_keep > 0, &listed) {
+                self.prune(listed);
+            }
             self.save().await?;
         }
     }
 
-    #[tracing::instrument]
-    async fn restore(&mut self) -> Result<(), Err> {
-        // Restore from file
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open("questions.json")
-            .await?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).await?;
-        self.questions = serde_json::from_str(&contents).unwrap_or_default();
-        info!("Restored {} questions", self.questions.len());
+    /// When the next question will be posted, skipping excluded dates and days already posted on
+    pub fn next_post_instant(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        if let Some(schedule) = &self.schedule {
+            return schedule
+                .after(&(now - Duration::minutes(1)).with_timezone(&self.timezone))
+                .map(|fire| fire.with_timezone(&Utc))
+                .take(MAX_SCHEDULE_LOOKAHEAD)
+                .find(|fire| !self.posted_since(*fire) && !self.is_excluded(fire.date_naive()))
+                .unwrap_or(now);
+        }
+
+        let mut date = now.date_naive();
+        loop {
+            let instant = date.and_time(self.post_at).and_utc();
+            // Posts go out at any point during the post_at minute
+            if instant + Duration::minutes(1) > now
+                && !self.posted_on(date)
+                && !self.is_excluded(date)
+            {
+                return instant;
+            }
+
+            date = match date.succ_opt() {
+                Some(date) => date,
+                None => return instant,
+            };
+        }
+    }
+
+    /// Keeps other processes from changing the state until the lock is dropped. Whoever
+    /// restores, changes and saves the state holds it, so no one saves over another's change.
+    pub async fn lock(&self) -> Result<StateLock, Err> {
+        self.storage.lock().await
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn restore(&mut self) -> Result<(), Err> {
+        self.state = self.storage.load().await?;
+        self.norm_index = self
+            .state
+            .questions
+            .iter()
+            .map(|q| (normalize(&q.text), q.id))
+            .collect();
+        if self.state.streak_posted_on.is_none() {
+            self.streak_from_history();
+        }
+        info!("Restored {} questions", self.state.questions.len());
 
         Ok(())
     }
 
-    #[tracing::instrument]
-    async fn save(&mut self) -> Result<(), Err> {
-        // Save to file
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open("questions.json")
-            .await?;
-        let json = serde_json::to_string(&self.questions)?;
-        file.set_len(0).await?;
-        file.seek(std::io::SeekFrom::Start(0)).await?;
-        file.write_all(json.as_bytes()).await?;
-        info!("Saved {} questions", self.questions.len());
+    #[tracing::instrument(skip(self))]
+    pub async fn save(&mut self) -> Result<(), Err> {
+        if let Err(e) = self.storage.save(&self.state).await {
+            error!("Saving the state failed: {}", e);
+            return Err(e);
+        }
+        info!("Saved {} questions", self.state.questions.len());
 
         Ok(())
     }
 
-    #[tracing::instrument]
-    async fn load(&mut self) -> Result<(), Err> {
-        let mut response = isahc::get_async(&self.url).await?;
-        let raw = response.text().await?;
-        let raw_questions = raw.split("\n");
+    /// Merges the sources into the state, returning the questions they list unless one
+    /// of them came back empty
+    #[tracing::instrument(skip(self), fields(new_questions_added, questions_updated))]
+    async fn load(&mut self) -> Result<Option<HashSet<Uuid>>, Err> {
+        let now = Utc::now();
+        if self
+            .last_fetch_time
+            .is_some_and(|last| now - last < self.cache_source_ttl)
+        {
+            debug!("Skipping source load: fetched less than the cache TTL ago");
+            return Ok(None);
+        }
 
-        for question in raw_questions {
-            match self.questions.iter_mut().find(|q| q.distance(question) < 4) {
-                Some(q) => {
-                    if q.distance(question) != 0 {
-                        info!("Updating existing question {}", q.id);
-                        q.text = question.trim().to_string()
+        let mut listed = HashSet::new();
+        let mut complete = true;
+        let (mut added, mut updated) = (0, 0);
+        let sources = self.fetch_sources().await?;
+        self.last_fetch_time = Some(now);
+        for raw in sources {
+            let entries = self.limit(self.source_format.parse(&raw, &self.plain_options)?);
+            // Far more likely a broken source than one that was emptied on purpose
+            if entries.is_empty() {
+                warn!("A source has no questions, not pruning any this tick");
+                complete = false;
+            }
+            let merged = self.merge(entries);
+            added += merged.added;
+            updated += merged.updated;
+            listed.extend(merged.listed);
+        }
+
+        let span = tracing::Span::current();
+        span.record("new_questions_added", added);
+        span.record("questions_updated", updated);
+
+        Ok(complete.then_some(listed))
+    }
+
+    /// Drops entries beyond the caps, since every entry is compared against every stored question
+    fn limit(&self, mut entries: Vec<Entry>) -> Vec<Entry> {
+        if entries.len() > self.max_source_lines {
+            warn!(
+                "Source has {} questions, only the first {} are read",
+                entries.len(),
+                self.max_source_lines
+            );
+            entries.truncate(self.max_source_lines);
+        }
+
+        entries.retain(|entry| {
+            let length = entry.text.chars().count();
+            if length > self.max_source_line_length {
+                warn!(
+                    "Skipping a question of {} characters, longer than {}",
+                    length, self.max_source_line_length
+                );
+                return false;
+            }
+
+            true
+        });
+
+        entries
+    }
+
+    /// Checks that the webhooks exist, the sources can be reached and the state can be
+    /// saved, without posting or changing anything
+    pub async fn check_config(&self) -> Vec<ConfigCheck> {
+        let hooks = [
+            Some(("question webhook", &self.hook)),
+            self.notification_hook
+                .as_ref()
+                .map(|hook| ("notification webhook", hook)),
+            self.summary_hook
+                .as_ref()
+                .map(|hook| ("summary webhook", hook)),
+        ];
+        let webhooks = hooks.into_iter().flatten().map(|(target, hook)| {
+            ConfigCheck::run("webhook", target.to_string(), hook.test_connectivity())
+        });
+        let sources = self
+            .urls
+            .iter()
+            .map(|url| ConfigCheck::run("source", url.clone(), self.check_source(url)));
+        let storage = ConfigCheck::run(
+            "questions file",
+            self.storage.location(),
+            self.storage.check_writable(),
+        );
+
+        let (webhooks, sources, storage) =
+            futures::join!(join_all(webhooks), join_all(sources), storage);
+        webhooks
+            .into_iter()
+            .chain(sources)
+            .chain([storage])
+            .collect()
+    }
+
+    /// Sends a HEAD request to HTTP sources, anything else is fetched
+    async fn check_source(&self, url: &str) -> Result<(), Err> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            self.fetcher.fetch(url).await?;
+            return Ok(());
+        }
+
+        let request = isahc::Request::head(url).body(())?;
+        let response = isahc::send_async(request).await?;
+        if !response.status().is_success() {
+            return Err(format!("HEAD returned {}", response.status()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the sources and lists everything that looks wrong with them, without touching the state
+    pub async fn validate_sources(&self) -> Result<Vec<SourceReport>, Err> {
+        let mut urls = self.urls.clone();
+        urls.sort();
+
+        let mut reports = vec![];
+        for url in urls {
+            let raw = self.fetcher.fetch(&url).await?;
+            let mut report = SourceReport {
+                url,
+                questions: 0,
+                problems: vec![],
+            };
+            let entries = match self.source_format.parse(&raw, &self.plain_options) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    report.problems.push(format!(
+                        "failed to parse as {:?}: {}",
+                        self.source_format, e
+                    ));
+                    reports.push(report);
+                    continue;
+                }
+            };
+
+            if self.source_format == SourceFormat::Plain {
+                let lines = raw.trim_end().split('\n').collect::<Vec<&str>>();
+                for (number, line) in lines.iter().enumerate() {
+                    if line.trim().is_empty() {
+                        report
+                            .problems
+                            .push(format!("line {} is blank", number + 1));
                     }
                 }
-                None => {
-                    let new_question = Question::new(question.trim().into());
-                    info!("Adding new question {}", &new_question.id);
-                    self.questions.push(new_question);
+            }
+
+            if entries.len() > self.max_source_lines {
+                report.problems.push(format!(
+                    "has {} questions, only the first {} are read",
+                    entries.len(),
+                    self.max_source_lines
+                ));
+            }
+            for entry in entries.iter().take(self.max_source_lines) {
+                let length = entry.text.chars().count();
+                if length > self.max_source_line_length {
+                    report.problems.push(format!(
+                        "\"{}\" is {} characters long and skipped, the limit is {}",
+                        entry.text, length, self.max_source_line_length
+                    ));
+                }
+            }
+
+            // Only what a load would merge is compared for duplicates
+            let questions = self
+                .limit(entries)
+                .into_iter()
+                .map(Question::from)
+                .collect::<Vec<Question>>();
+            report.questions = questions.len();
+            for (i, question) in questions.iter().enumerate() {
+                if let Some(original) = questions[..i]
+                    .iter()
+                    .find(|q| q.matches(&question.text, &self.dedup))
+                {
+                    report.problems.push(format!(
+                        "\"{}\" duplicates \"{}\"",
+                        question.text, original.text
+                    ));
                 }
             }
+            reports.push(report);
         }
 
-        Ok(())
+        Ok(reports)
     }
 
-    #[tracing::instrument]
-    async fn answer(&mut self) -> Result<(), Err> {
-        let mut unanswered_questions = self
-            .questions
-            .iter_mut()
-            .filter(|q| !q.answered)
-            .collect::<Vec<&mut Question>>();
+    fn record(&mut self, event: AuditEvent) {
+        self.record_entry(AuditEntry::new(event));
+    }
 
-        if unanswered_questions.len() == 0 {
-            info!("No unanswered questions");
-            return Ok(());
+    fn record_entry(&mut self, entry: AuditEntry) {
+        let audit_log = &mut self.state.audit_log;
+        audit_log.push(entry);
+        if audit_log.len() > self.audit_log_size {
+            let excess = audit_log.len() - self.audit_log_size;
+            audit_log.drain(..excess);
         }
+    }
 
-        let mut rng = rand::thread_rng();
-        unanswered_questions.shuffle(&mut rng);
-
-        let question = unanswered_questions.first_mut().unwrap();
-        info!("{}", question.text);
+    /// Fetches every source, ordered by URL so merging is reproducible
+    async fn fetch_sources(&self) -> Result<Vec<String>, Err> {
+        let mut urls = self.urls.clone();
+        urls.sort();
 
-        self.hook.send(question.text.clone()).await?;
+        if !self.concurrent_sources {
+            let mut bodies = vec![];
+            for url in &urls {
+                bodies.push(self.fetcher.fetch(url).await?);
+            }
+            return Ok(bodies);
+        }
 
-        question.answered = true;
+        let semaphore = Arc::new(Semaphore::new(self.max_concurrent_fetches.max(1)));
+        let tasks = urls.into_iter().map(|url| {
+            let semaphore = semaphore.clone();
+            let fetcher = self.fetcher.clone();
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                fetcher.fetch(&url).await
+            })
+        });
 
-        Ok(())
+        join_all(tasks)
+            .await
+            .into_iter()
+            .map(|body| body?)
+            .collect()
     }
-}
 
-impl fmt::Debug for Bot {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Bot {{ url: {} }}", self.url)
+    fn merge(&mut self, entries: Vec<Entry>) -> Merge {
+        let mut events = vec![];
+        let mut merged = Merge::default();
+        for entry in entries {
+            let key = normalize(&entry.text);
+            if self.state.deleted.contains(&key) {
+                continue;
+            }
+
+            let existing = self
+                .duplicate_of(&entry.text, true)
+                .and_then(|id| self.state.questions.iter_mut().find(|q| q.id == id));
+            match existing {
+                Some(q) => {
+                    merged.listed.push(q.id);
+                    if q.differs_from(&entry) {
+                        info!(
+                            "Updating existing question {} (similarity {:.2})",
+                            q.id,
+                            q.similarity_with(&entry.text, self.dedup.algorithm)
+                        );
+                        self.norm_index.remove(&normalize(&q.text));
+                        q.update(entry);
+                        self.norm_index.insert(key, q.id);
+                        events.push(AuditEvent::QuestionUpdated(q.id));
+                        merged.updated += 1;
+                    }
+                }
+                None => {
+                    let id = self.add(entry);
+                    events.push(AuditEvent::QuestionAdded(id));
+                    merged.listed.push(id);
+                    merged.added += 1;
+                }
+            }
+        }
+
+        for event in events {
+            self.record(event);
+        }
+
+        merged
     }
-}
 
-impl Question {
-    pub fn new(text: String) -> Question {
-        Question {
-            id: Uuid::new_v4(),
-            text,
-            answered: false,
+    /// The stored question `text` duplicates. Identical text is found through the index, and
+    /// only near matches, when they count, need a full scan.
+    fn duplicate_of(&self, text: &str, near_matches: bool) -> Option<Uuid> {
+        // Case variants share an index key, so exact mode looks them up by their text
+        if self.dedup.exact {
+            return self
+                .state
+                .questions
+                .iter()
+                .find(|q| q.text == text)
+                .map(|q| q.id);
+        }
+        if let Some(id) = self.norm_index.get(&normalize(text)) {
+            return Some(*id);
         }
+        if !near_matches || self.dedup.strict_only {
+            return None;
+        }
+
+        self.state
+            .questions
+            .iter()
+            .find(|q| q.matches(text, &self.dedup))
+            .map(|q| q.id)
     }
 
-    fn distance(&self, other: &str) -> usize {
-        //Damerau-Levenshtein distance
-        if self.text == other {
+    fn add(&mut self, entry: Entry) -> Uuid {
+        let question = Question::from(entry);
+        let id = question.id;
+        info!("Adding new question {}", id);
+        self.norm_index.insert(normalize(&question.text), id);
+        self.state.questions.push(question);
+
+        id
+    }
+
+    /// Drops answered questions beyond the most recent `answered_questions_keep`.
+    /// Questions the sources still list are kept, since the next load would add them back as new.
+    fn prune(&mut self, listed: &HashSet<Uuid>) -> usize {
+        let mut answered = self
+            .state
+            .questions
+            .iter()
+            .filter(|q| q.answered && !q.pinned)
+            .collect::<Vec<&Question>>();
+        answered.sort_by_key(|q| std::cmp::Reverse(q.answered_at));
+
+        let pruned = answered
+            .into_iter()
+            .skip(self.answered_questions_keep)
+            .filter(|q| !listed.contains(&q.id))
+            .map(|q| q.id)
+            .collect::<HashSet<Uuid>>();
+        if pruned.is_empty() {
             return 0;
         }
 
-        if self.text.len() == 0 {
-            return other.len();
+        self.state.questions.retain(|q| !pruned.contains(&q.id));
+        self.norm_index.retain(|_, id| !pruned.contains(id));
+        info!("Pruned {} answered questions", pruned.len());
+        let count = pruned.len();
+        for id in pruned {
+            self.record(AuditEvent::QuestionPruned(id));
+        }
+
+        count
+    }
+
+    /// What decides the next question, shared by the post and its preview so they agree
+    fn candidates(&self, now: DateTime<Utc>, exclude: &HashSet<Uuid>) -> Candidates {
+        let today = now.date_naive();
+        let unanswered = self.state.questions.iter().filter(|q| {
+            (q.is_eligible() || self.selection_mode == SelectionMode::AllowRepeats)
+                && q.scheduled_for.is_none_or(|date| date <= today)
+                && !exclude.contains(&q.id)
+        });
+
+        if let Some(question) = self
+            .state
+            .intro_question
+            .and_then(|id| unanswered.clone().find(|q| q.id == id))
+        {
+            return Candidates::Announced(question.id);
+        }
+
+        // Questions scheduled for today, or missed on an earlier day, come first
+        if let Some(question) = unanswered
+            .clone()
+            .filter(|q| q.scheduled_for.is_some() && !q.answered)
+            .min_by_key(|q| q.scheduled_for)
+        {
+            return Candidates::Scheduled(question.id, question.scheduled_for.unwrap_or(today));
         }
 
-        if other.len() == 0 {
-            return self.text.len();
+        if let Some(question) = unanswered.clone().find(|q| q.priority) {
+            return Candidates::Prioritized(question.id);
         }
 
-        let mut matrix = vec![vec![0; other.len() + 1]; self.text.len() + 1];
-        for i in 1..=self.text.len() {
-            matrix[i][0] = i;
-            for j in 1..=other.len() {
-                let cost = if self.text.chars().nth(i - 1) == other.chars().nth(j - 1) {
-                    0
-                } else {
-                    1
-                };
-                if i == 1 {
-                    matrix[0][j] = j;
+        let eligible = unanswered
+            .clone()
+            .filter(|q| !self.posted_within_window(q, now))
+            .map(|q| q.id)
+            .collect::<HashSet<Uuid>>();
+        if let (true, Some(days)) = (eligible.is_empty(), self.uniqueness_window_days) {
+            let recent = unanswered.count();
+            if recent > 0 {
+                warn!(
+                    "All {} eligible questions were posted within the last {} days",
+                    recent, days
+                );
+            }
+        }
+        if self.explain {
+            info!("{}", self.eligibility(now, exclude));
+        }
+
+        Candidates::Eligible(eligible)
+    }
+
+    /// How many questions are eligible, and why the others aren't. Each counts for the first
+    /// reason in the order `candidates` checks them.
+    fn eligibility(&self, now: DateTime<Utc>, exclude: &HashSet<Uuid>) -> String {
+        let today = now.date_naive();
+        let (mut eligible, mut answered, mut later, mut batch, mut recent) = (0, 0, 0, 0, 0);
+        for question in &self.state.questions {
+            if !question.is_eligible() && self.selection_mode != SelectionMode::AllowRepeats {
+                answered += 1;
+            } else if question.scheduled_for.is_some_and(|date| date > today) {
+                later += 1;
+            } else if exclude.contains(&question.id) {
+                batch += 1;
+            } else if self.posted_within_window(question, now) {
+                recent += 1;
+            } else {
+                eligible += 1;
+            }
+        }
+
+        format!(
+            "{} of {} questions are eligible, {} were answered, {} are scheduled later, {} are \
+             in this batch and {} were posted within the uniqueness window",
+            eligible,
+            self.state.questions.len(),
+            answered,
+            later,
+            batch,
+            recent
+        )
+    }
+
+    /// The question the next post will use, refilling the bag once a cycle is over.
+    /// Questions in `exclude` were already posted in the same batch.
+    fn next_question_id(&mut self, now: DateTime<Utc>, exclude: &HashSet<Uuid>) -> Option<Uuid> {
+        let eligible = match self.candidates(now, exclude) {
+            Candidates::Announced(id) => {
+                if self.explain {
+                    info!("Picked {}, the intro announced it", id);
+                }
+                return Some(id);
+            }
+            Candidates::Scheduled(id, date) => {
+                if self.explain {
+                    info!("Picked {}, it is scheduled for {}", id, date);
+                }
+                return Some(id);
+            }
+            Candidates::Prioritized(id) => {
+                if self.explain {
+                    info!("Picked {}, it was prioritized", id);
                 }
+                return Some(id);
+            }
+            Candidates::Eligible(eligible) => eligible,
+        };
 
-                let vals = [
-                    matrix[i - 1][j] + 1,
-                    matrix[i][j - 1] + 1,
-                    matrix[i - 1][j - 1] + cost,
-                ];
-                matrix[i][j] = *vals.iter().min().unwrap();
-                if i > 1
-                    && j > 1
-                    && self.text.chars().nth(i - 1) == other.chars().nth(j - 2)
-                    && self.text.chars().nth(i - 2) == other.chars().nth(j - 1)
-                {
-                    matrix[i][j] = std::cmp::min(matrix[i][j], matrix[i - 2][j - 2] + cost);
+        match self.selection_mode {
+            SelectionMode::ShuffledCycle => {}
+            SelectionMode::AllowRepeats => return self.pick_at_random(&eligible, now),
+            SelectionMode::Sequential => {
+                let picked = self.first_in_order(&eligible)?;
+                if self.explain {
+                    info!("Picked {}, sort order {}", picked.id, picked.sort_order);
+                }
+                return Some(picked.id);
+            }
+        }
+
+        // Draw from a shuffled bag so every question comes up once before any repeats
+        let bag = &mut self.state.bag;
+        bag.retain(|id| eligible.contains(id));
+        if bag.is_empty() {
+            if self.explain {
+                info!("Cycle finished, reshuffling {} questions", eligible.len());
+            }
+            bag.extend(eligible.iter());
+            bag.shuffle(&mut rand::thread_rng());
+        }
+
+        let picked = self.draw_from_bag(&eligible, now);
+        if self.explain {
+            let cooling = self.cooling_categories(now);
+            let skipped = self
+                .state
+                .bag
+                .iter()
+                .filter(|id| self.category_of(id).is_some_and(|c| cooling.contains(c)))
+                .count();
+            info!(
+                "{} questions left in this cycle, {} of them skipped for category cooldowns {:?}",
+                self.state.bag.len(),
+                skipped,
+                cooling
+            );
+            match picked {
+                Some(id) if skipped == self.state.bag.len() => {
+                    info!("Picked {}, every remaining category is cooling down", id)
                 }
+                Some(id) => info!("Picked {}, the next question in the shuffled bag", id),
+                None => info!("Nothing to pick"),
             }
         }
 
-        matrix[self.text.len()][other.len()]
+        picked
     }
-}
 
-impl Webhook {
-    pub fn new(id: u64, token: String) -> Self {
-        Self { id, token }
+    /// The question the next post will pick, without drawing it. None when that's
+    /// left to chance, with repeats allowed or a cycle that still has to be shuffled.
+    pub fn peek_next_question(&self, now: DateTime<Utc>) -> Option<&Question> {
+        let id = match self.candidates(now, &HashSet::new()) {
+            Candidates::Announced(id)
+            | Candidates::Scheduled(id, _)
+            | Candidates::Prioritized(id) => id,
+            Candidates::Eligible(eligible) => match self.selection_mode {
+                SelectionMode::AllowRepeats => return None,
+                SelectionMode::Sequential => return self.first_in_order(&eligible),
+                SelectionMode::ShuffledCycle => self.draw_from_bag(&eligible, now)?,
+            },
+        };
+
+        self.state.questions.iter().find(|q| q.id == id)
     }
 
-    async fn send(&self, text: String) -> Result<(), Err> {
-        let http = serenity::http::Http::new_with_token(&self.token);
-        let webhook = http.get_webhook_with_token(self.id, &self.token).await?;
+    fn first_in_order(&self, eligible: &HashSet<Uuid>) -> Option<&Question> {
+        self.questions_in_order()
+            .into_iter()
+            .find(|q| eligible.contains(&q.id))
+    }
 
-        let embed = Embed::fake(|e| {
-            e.title(":question: :grey_question: Question of the day :grey_question: :question:");
-            e.description(text + "\n\u{200B}");
-            e.colour(0xff0000);
-            e.footer(|f| {
-                f.text(format!(
-                    "Asked by Hawk's bot at {}",
-                    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S")
-                ));
-                f
+    /// The next of `eligible` in the bag, skipping categories that came up too recently
+    /// unless every one of them did. None once the bag holds none of them.
+    fn draw_from_bag(&self, eligible: &HashSet<Uuid>, now: DateTime<Utc>) -> Option<Uuid> {
+        let cooling = self.cooling_categories(now);
+        let mut in_bag = self
+            .state
+            .bag
+            .iter()
+            .rev()
+            .filter(|id| eligible.contains(id));
+        in_bag
+            .clone()
+            .find(|id| !self.category_of(id).is_some_and(|c| cooling.contains(c)))
+            .or_else(|| in_bag.next())
+            .copied()
+    }
+
+    fn category_of(&self, id: &Uuid) -> Option<&String> {
+        self.state
+            .questions
+            .iter()
+            .find(|q| q.id == *id)
+            .and_then(|q| q.category.as_ref())
+    }
+
+    /// Picks any of `eligible`, from a category that isn't cooling down if there is one
+    fn pick_at_random(&self, eligible: &HashSet<Uuid>, now: DateTime<Utc>) -> Option<Uuid> {
+        let cooling = self.cooling_categories(now);
+        let (ready, cooling) = self
+            .state
+            .questions
+            .iter()
+            .filter(|q| eligible.contains(&q.id))
+            .partition::<Vec<&Question>, _>(|q| {
+                !q.category.as_ref().is_some_and(|c| cooling.contains(c))
             });
-            e
-        });
 
-        webhook
-            .execute(&http, false, |w| {
-                w.username("Question of the day");
-                w.embeds(vec![embed]);
-                w
+        let mut rng = rand::thread_rng();
+        let picked = ready
+            .choose(&mut rng)
+            .or_else(|| cooling.choose(&mut rng))?;
+        if self.explain {
+            info!("Picked {} at random, repeats are allowed", picked.id);
+        }
+
+        Some(picked.id)
+    }
+
+    fn posted_within_window(&self, question: &Question, now: DateTime<Utc>) -> bool {
+        match (self.uniqueness_window_days, question.answered_at) {
+            (Some(days), Some(answered_at)) => now - answered_at < Duration::days(days.into()),
+            _ => false,
+        }
+    }
+
+    /// Categories that were posted from within their cooldown
+    fn cooling_categories(&self, now: DateTime<Utc>) -> HashSet<&String> {
+        self.category_cooldowns
+            .iter()
+            .filter(|cooldown| {
+                self.state
+                    .category_last_posted
+                    .get(&cooldown.category)
+                    .is_some_and(|posted_at| now - *posted_at < Duration::days(cooldown.days))
             })
-            .await?;
+            .map(|cooldown| &cooldown.category)
+            .collect()
+    }
+
+    /// Announces the category of the upcoming question `intro_offset` before it is posted
+    async fn intro(&mut self, now: DateTime<Utc>) -> Result<(), Err> {
+        let id = match self.announce(now) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let category = self
+            .state
+            .questions
+            .iter()
+            .find(|q| q.id == id)
+            .and_then(|q| q.category.clone());
+        self.save().await?;
+
+        self.hook
+            .send_intro(&self.template, category.as_deref())
+            .await
+    }
+
+    /// Settles the question the intro in the minute of `now` announces, if one is due
+    fn announce(&mut self, now: DateTime<Utc>) -> Option<Uuid> {
+        let intro_at = self.post_at - self.intro_offset;
+        if now.hour() != intro_at.hour() || now.minute() != intro_at.minute() {
+            return None;
+        }
+
+        let today = now.date_naive();
+        // The intro falls on the day before the post when the offset crosses midnight
+        let post_date = if intro_at > self.post_at {
+            today.succ_opt().unwrap_or(today)
+        } else {
+            today
+        };
+        if self.state.intro_posted_on == Some(today)
+            || self.posted_on(post_date)
+            || self.is_excluded(post_date)
+        {
+            return None;
+        }
+
+        let id = self.next_question_id(now, &HashSet::new())?;
+        self.state.intro_posted_on = Some(today);
+        self.state.intro_question = Some(id);
+
+        Some(id)
+    }
+
+    /// Posts `questions_per_post` questions, or as many as are left
+    async fn answer(&mut self, now: DateTime<Utc>) -> Result<(), Err> {
+        if let Some(text) = self.pre_post_question().await {
+            if let Some(id) = self.submit(text, None) {
+                info!("Posting question {} from the pre-post command", id);
+                self.prioritize(&id.to_string())?;
+            }
+        }
+
+        let mut posted = HashSet::new();
+        for _ in 0..self.questions_per_post.max(1) {
+            match self.answer_one(now, &posted).await? {
+                Some(id) => posted.insert(id),
+                None => break,
+            };
+        }
+        self.alert_low_watermark().await;
 
         Ok(())
     }
+
+    /// What `pre_post_command` printed, None when it printed nothing, failed or took too long
+    async fn pre_post_question(&self) -> Option<String> {
+        let command = self.pre_post_command.as_ref()?;
+        let output = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .kill_on_drop(true)
+            .output();
+        let output = match time::timeout(self.pre_post_command_timeout, output).await {
+            Ok(Ok(output)) if output.status.success() => output,
+            Ok(Ok(output)) => {
+                warn!("Pre-post command failed with {}", output.status);
+                return None;
+            }
+            Ok(Err(e)) => {
+                warn!("Couldn't run the pre-post command: {}", e);
+                return None;
+            }
+            Err(_) => {
+                warn!(
+                    "Pre-post command timed out after {:?}",
+                    self.pre_post_command_timeout
+                );
+                return None;
+            }
+        };
+
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// Posts the next question that isn't in `posted`, returning None if there was none to post
+    #[tracing::instrument(skip(self), fields(selected_uuid))]
+    async fn answer_one(
+        &mut self,
+        now: DateTime<Utc>,
+        posted: &HashSet<Uuid>,
+    ) -> Result<Option<Uuid>, Err> {
+        let id = match self.next_question_id(now, posted) {
+            Some(id) => {
+                tracing::Span::current().record("selected_uuid", id.to_string());
+                id
+            }
+            None if posted.is_empty() => {
+                info!("No eligible questions");
+                self.notify("No eligible questions left to post").await;
+                return Ok(None);
+            }
+            None => {
+                info!("No more eligible questions for this batch");
+                return Ok(None);
+            }
+        };
+        self.state.bag.retain(|bag_id| *bag_id != id);
+
+        let question = self
+            .state
+            .questions
+            .iter_mut()
+            .find(|q| q.id == id)
+            .unwrap();
+        info!("{}", question.text);
+
+        if let (Some(key), true, Some(category), None) = (
+            &self.unsplash_api_key,
+            self.unsplash_query_from_category,
+            &question.category,
+            &question.embed_image_url,
+        ) {
+            let image = self
+                .hook
+                .http_policy
+                .run("Unsplash request", || unsplash_image(key, category))
+                .await
+                .map_err(Err::from)
+                .and_then(|image| image);
+            match image {
+                Ok(url) => question.embed_image_url = Some(url),
+                Err(e) => warn!("Failed to fetch an image for {}: {}", question.id, e),
+            }
+        }
+
+        // Mark the question as posted and persist that before sending, so a send whose
+        // response got lost can't make us post again after a retry or restart
+        self.mark_posted(id, now);
+        self.save().await?;
+
+        if let Err(e) = self.send_question(id).await {
+            // A send that timed out may have been posted, only one Discord turned away is repeated
+            if e.is::<Unavailable>() {
+                self.unsent = Some(id);
+            }
+            return Err(e);
+        }
+
+        Ok(Some(id))
+    }
+
+    /// Sends the question Discord turned away on the last tick again
+    async fn retry_unsent(&mut self) {
+        if let Some(id) = self.unsent.take() {
+            info!("Sending question {} again", id);
+            match self.send_question(id).await {
+                Ok(()) => {}
+                Err(e) if e.is::<Unavailable>() => {
+                    warn!("Posting question {} failed again: {}", id, e);
+                    self.unsent = Some(id);
+                }
+                Err(e) => warn!("Posting question {} failed: {}", id, e),
+            }
+        }
+    }
+
+    /// Sends a question that was already marked posted, remembering the message it went out as
+    async fn send_question(&mut self, id: Uuid) -> Result<(), Err> {
+        let question = match self.state.questions.iter().find(|q| q.id == id) {
+            Some(question) => question,
+            None => return Ok(()),
+        };
+        let message = if question.poll_options.is_empty() {
+            let number = self
+                .template
+                .show_question_number
+                .then(|| self.question_number(id));
+            let post_count = self
+                .template
+                .show_post_count
+                .then_some(self.state.post_count);
+            self.hook
+                .send_embed_from_template(&self.template, question, number, post_count)
+                .await?
+        } else {
+            self.hook
+                .send_poll(
+                    question.localized_text(self.template.variant),
+                    &question.poll_options,
+                )
+                .await?
+        };
+        if let (Some(message), Some(question)) = (
+            message,
+            self.state.questions.iter_mut().find(|q| q.id == id),
+        ) {
+            question.channel_id = Some(message.channel_id);
+            question.message_id = Some(message.id);
+        }
+
+        Ok(())
+    }
+
+    /// Posts the questions of the month so far on the recap day, once the post time has passed
+    async fn monthly_recap(&mut self, now: DateTime<Utc>) -> Result<(), Err> {
+        let today = now.date_naive();
+        if self.monthly_recap_day != Some(today.day())
+            || now.time() < self.post_at
+            || self.state.recap_posted_on == Some(today)
+        {
+            return Ok(());
+        }
+
+        self.state.recap_posted_on = Some(today);
+        self.save().await?;
+
+        let month = today.with_day(1).unwrap_or(today);
+        let questions = self.history_in_range(month, today);
+        if questions.is_empty() {
+            return Ok(());
+        }
+        info!("Posting the recap of {} questions", questions.len());
+        self.hook
+            .send_monthly_recap(&self.template, month, &questions)
+            .await
+    }
+
+    /// Logs the settings that most often turn out to be wrong, leaving out every token
+    fn log_config(&self) {
+        let webhooks =
+            1 + self.notification_hook.is_some() as usize + self.summary_hook.is_some() as usize;
+        // The sources are already on the span of start
+        info!(
+            post_at = %self.post_at,
+            schedule = %self.schedule.as_ref().map_or("-".to_string(), |s| s.to_string()),
+            timezone = %self.timezone,
+            state = %self.storage.location(),
+            webhooks,
+            selection_mode = ?self.selection_mode,
+            "Starting with this configuration"
+        );
+    }
+
+    /// Counts the reactions on the questions posted long enough ago, once per question
+    async fn count_reactions(&mut self, now: DateTime<Utc>) {
+        let due = self
+            .state
+            .questions
+            .iter()
+            .filter(|q| q.reaction_count.is_none())
+            .filter(|q| {
+                q.answered_at
+                    .is_some_and(|answered_at| now - answered_at >= self.fetch_reactions_after)
+            })
+            .filter_map(|q| {
+                let message = PostedMessage {
+                    channel_id: q.channel_id?,
+                    id: q.message_id?,
+                };
+                Some((q.id, message))
+            })
+            .collect::<Vec<(Uuid, PostedMessage)>>();
+
+        for (id, message) in due {
+            match self.hook.reaction_count(message).await {
+                Ok(count) => {
+                    if let Some(question) = self.state.questions.iter_mut().find(|q| q.id == id) {
+                        question.reaction_count = Some(count);
+                    }
+                }
+                Err(e) => warn!("Failed to count the reactions on {}: {}", id, e),
+            }
+        }
+    }
+
+    /// Posts the three questions of the past week with the most reactions, on Mondays
+    async fn weekly_summary(&mut self, now: DateTime<Utc>) -> Result<(), Err> {
+        let today = now.date_naive();
+        if today.weekday() != Weekday::Mon
+            || now.time() < self.post_at
+            || self.state.summary_posted_on == Some(today)
+        {
+            return Ok(());
+        }
+
+        self.state.summary_posted_on = Some(today);
+        self.save().await?;
+
+        let questions = self.summary_questions(now);
+        if questions.is_empty() {
+            return Ok(());
+        }
+
+        match &self.summary_hook {
+            Some(hook) => hook.send_leaderboard(&self.template, &questions).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The three questions with the most reactions among a week of posts. The week ends where
+    /// reactions start being counted, so none of them is missing its count.
+    fn summary_questions(&self, now: DateTime<Utc>) -> Vec<&Question> {
+        let end = now - self.fetch_reactions_after;
+        let start = end - Duration::days(7);
+        let mut questions = self
+            .state
+            .questions
+            .iter()
+            .filter(|q| {
+                q.answered_at
+                    .is_some_and(|answered_at| start <= answered_at && answered_at < end)
+            })
+            .filter(|q| q.reaction_count.is_some_and(|count| count > 0))
+            .collect::<Vec<&Question>>();
+        questions.sort_by_key(|q| (std::cmp::Reverse(q.reaction_count), q.answered_at));
+        questions.truncate(3);
+
+        questions
+    }
+
+    /// Posts the next question outside of the schedule
+    async fn post_now(&mut self) -> Result<(), Err> {
+        info!("Posting on request");
+        let _lock = self.lock().await?;
+        self.restore().await?;
+        self.answer(Utc::now()).await?;
+        self.save().await
+    }
+
+    /// Asks for more questions the first time the unanswered ones drop below the watermark
+    async fn alert_low_watermark(&mut self) {
+        let pending = self.pending_count();
+        if pending >= self.low_watermark || self.state.low_watermark_alerted {
+            return;
+        }
+
+        let text = format!(
+            "Only {} unanswered questions left, please add more.",
+            pending
+        );
+        match self
+            .notifier()
+            .send_announcement(&self.template, &text)
+            .await
+        {
+            Ok(()) => self.state.low_watermark_alerted = true,
+            Err(e) => warn!("Failed to send the low watermark alert: {}", e),
+        }
+    }
+
+    /// Where announcements go, the notification webhook if there is one
+    fn notifier(&self) -> &Webhook {
+        self.notification_hook.as_ref().unwrap_or(&self.hook)
+    }
+
+    /// Tells the operators about a problem, in the question channel unless they have one of their own
+    async fn notify(&self, text: &str) {
+        if let Err(e) = self
+            .notifier()
+            .send_announcement(&self.template, text)
+            .await
+        {
+            warn!("Failed to send a notification: {}", e);
+        }
+    }
+
+    /// Saved together with the question, so a retried send can't count a post twice
+    fn mark_posted(&mut self, id: Uuid, now: DateTime<Utc>) {
+        self.state.post_count += 1;
+        self.state.intro_question = None;
+        if let Some(question) = self.state.questions.iter_mut().find(|q| q.id == id) {
+            question.answered = true;
+            question.answered_at = Some(now);
+            question.priority = false;
+            if let Some(category) = &question.category {
+                self.state
+                    .category_last_posted
+                    .insert(category.clone(), now);
+            }
+        }
+        self.extend_streak(now.date_naive());
+        // Stamped with the tick's time, which a dry run sets, so the log says when it was posted
+        self.record_entry(AuditEntry::at(AuditEvent::QuestionPosted(id), now));
+    }
+
+    /// Whether a question should be posted in the minute of `now`
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        if self.state.questions.is_empty() {
+            return false;
+        }
+
+        match &self.schedule {
+            // Ticks are a minute apart, so a fire time within the last minute is due now
+            Some(schedule) => schedule
+                .after(&(now - Duration::minutes(1)).with_timezone(&self.timezone))
+                .next()
+                .map(|fire| fire.with_timezone(&Utc))
+                .is_some_and(|fire| fire <= now && !self.posted_since(fire)),
+            None => {
+                now.hour() == self.post_at.hour()
+                    && now.minute() == self.post_at.minute()
+                    && !self.posted_on(now.date_naive())
+            }
+        }
+    }
+
+    /// Whether the schedule posted at or after `instant`. Manual posts don't count, so one
+    /// can't take the place of the scheduled post.
+    fn posted_since(&self, instant: DateTime<Utc>) -> bool {
+        self.state
+            .last_scheduled
+            .is_some_and(|last| last >= instant)
+    }
+
+    /// Runs one tick at `now` against the in-memory state, with `source` standing in for the
+    /// fetched sources. Nothing is sent and storage is never read or written.
+    pub fn dry_run_tick(&mut self, now: DateTime<Utc>, source: &str) -> Result<TickResult, Err> {
+        let entries = self.limit(self.source_format.parse(source, &self.plain_options)?);
+        let empty = entries.is_empty();
+        let merged = self.merge(entries);
+
+        let mut intro_announced = None;
+        if self.intro_offset > Duration::zero() && self.schedule.is_none() && !self.state.paused {
+            intro_announced = self.announce(now);
+        }
+
+        let mut question_posted = None;
+        if self.is_due(now) && !self.state.paused && !self.is_excluded(now.date_naive()) {
+            question_posted = self.next_question_id(now, &HashSet::new());
+            self.state.last_scheduled = Some(now);
+            if let Some(id) = question_posted {
+                self.state.bag.retain(|bag_id| *bag_id != id);
+                self.mark_posted(id, now);
+            }
+        }
+
+        let questions_removed = match (self.answered_questions_keep, empty) {
+            (0, _) | (_, true) => 0,
+            _ => self.prune(&merged.listed.into_iter().collect()),
+        };
+
+        Ok(TickResult {
+            intro_announced,
+            question_posted,
+            questions_added: merged.added,
+            questions_updated: merged.updated,
+            questions_removed,
+        })
+    }
+
+    /// 1-based position of a question in the order questions were added, and the total count
+    fn question_number(&self, id: Uuid) -> (usize, usize) {
+        let mut ordered = self.state.questions.iter().collect::<Vec<&Question>>();
+        ordered.sort_by_key(|q| q.added_at);
+        let position = ordered.iter().position(|q| q.id == id).unwrap_or_default();
+
+        (position + 1, ordered.len())
+    }
+
+    fn is_excluded(&self, date: NaiveDate) -> bool {
+        self.exclude_dates.contains(&date)
+            || self.exclude_date_ranges.iter().any(|r| r.contains(date))
+    }
+
+    fn posted_on(&self, date: NaiveDate) -> bool {
+        self.state
+            .last_scheduled
+            .is_some_and(|last| last.date_naive() == date)
+    }
+
+    /// Number of consecutive days with a post, up to today or yesterday if today's post is
+    /// still to come. Excluded dates don't break the streak.
+    pub fn streak(&self, today: NaiveDate) -> usize {
+        match self.state.streak_posted_on {
+            Some(last) if last <= today && self.unbroken_between(last, today) => self.state.streak,
+            _ => 0,
+        }
+    }
+
+    /// Counts a post on `day` towards the streak
+    fn extend_streak(&mut self, day: NaiveDate) {
+        self.state.streak = match self.state.streak_posted_on {
+            // A second post on the same day, or one dated before the last, adds nothing
+            Some(last) if last >= day => return,
+            Some(last) if self.unbroken_between(last, day) => self.state.streak + 1,
+            _ => 1,
+        };
+        self.state.streak_posted_on = Some(day);
+    }
+
+    /// Whether every day strictly between `from` and `to` is excluded, so a streak carries over
+    fn unbroken_between(&self, from: NaiveDate, to: NaiveDate) -> bool {
+        from.iter_days()
+            .skip(1)
+            .take_while(|date| *date < to)
+            .all(|date| self.is_excluded(date))
+    }
+
+    /// Rebuilds the streak from the posts still in the state, for files saved before it was
+    /// kept there
+    fn streak_from_history(&mut self) {
+        let mut posted_days = self
+            .state
+            .questions
+            .iter()
+            .filter_map(|q| q.answered_at)
+            .map(|answered_at| answered_at.date_naive())
+            .collect::<Vec<NaiveDate>>();
+        posted_days.sort();
+        for day in posted_days {
+            self.extend_streak(day);
+        }
+    }
+
+    /// Questions posted between `from` and `to`, both inclusive, oldest first
+    pub fn history_in_range(&self, from: NaiveDate, to: NaiveDate) -> Vec<&Question> {
+        let mut questions = self
+            .state
+            .questions
+            .iter()
+            .filter(|q| {
+                q.answered_at.is_some_and(|answered_at| {
+                    from <= answered_at.date_naive() && answered_at.date_naive() <= to
+                })
+            })
+            .collect::<Vec<&Question>>();
+        questions.sort_by_key(|q| q.answered_at);
+
+        questions
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.state.questions.iter().filter(|q| !q.answered).count()
+    }
+
+    /// Questions posted on `date` in the order they went out, going by the audit log since a
+    /// repost moves a question's `answered_at` to the later day
+    pub fn questions_posted_on(&self, date: NaiveDate) -> Vec<&Question> {
+        let mut posted = vec![];
+        for entry in &self.state.audit_log {
+            let id = match entry.event {
+                AuditEvent::QuestionPosted(id) if entry.timestamp.date_naive() == date => id,
+                _ => continue,
+            };
+            if let Some(question) = self.state.questions.iter().find(|q| q.id == id) {
+                if !posted.iter().any(|q: &&Question| q.id == id) {
+                    posted.push(question);
+                }
+            }
+        }
+
+        posted
+    }
+
+    /// Adds a question that isn't in any source. Only the same text after normalization is
+    /// taken for a stored question, which then gets the category, so a near match can't
+    /// overwrite one.
+    pub fn submit(&mut self, text: String, category: Option<String>) -> Option<Uuid> {
+        if self.state.deleted.contains(&normalize(&text)) {
+            return None;
+        }
+
+        let id = match self.duplicate_of(&text, false) {
+            Some(id) => id,
+            None => {
+                let id = self.add(Entry {
+                    text,
+                    category,
+                    ..Default::default()
+                });
+                self.record(AuditEvent::QuestionAdded(id));
+                return Some(id);
+            }
+        };
+
+        let question = self.state.questions.iter_mut().find(|q| q.id == id)?;
+        if category.is_some() && question.category != category {
+            question.category = category;
+            self.record(AuditEvent::QuestionUpdated(id));
+        }
+
+        Some(id)
+    }
+
+    /// Adds a question to be posted on `date`, or schedules the stored one with the same text
+    pub fn schedule_question(
+        &mut self,
+        date: NaiveDate,
+        text: String,
+        category: Option<String>,
+    ) -> Option<Uuid> {
+        // A stored question keeps everything but the date, its category included
+        let id = match self.duplicate_of(&text, false) {
+            Some(id) => id,
+            None => self.submit(text, category)?,
+        };
+        let question = self.state.questions.iter_mut().find(|q| q.id == id)?;
+        question.scheduled_for = Some(date);
+        question.answered = false;
+        self.record(AuditEvent::QuestionUpdated(id));
+
+        Some(id)
+    }
+
+    /// Makes the question whose id starts with `prefix` the next one to be posted
+    pub fn prioritize(&mut self, prefix: &str) -> Result<Uuid, Err> {
+        let id = self.find_by_prefix(prefix)?;
+        for question in &mut self.state.questions {
+            question.priority = question.id == id;
+            if question.priority {
+                question.answered = false;
+            }
+        }
+        self.record(AuditEvent::QuestionUpdated(id));
+
+        Ok(id)
+    }
+
+    /// Moves the question whose id starts with `prefix` in the sequential order
+    pub fn reorder(&mut self, prefix: &str, sort_order: i64) -> Result<Uuid, Err> {
+        let id = self.find_by_prefix(prefix)?;
+        if let Some(question) = self.state.questions.iter_mut().find(|q| q.id == id) {
+            question.sort_order = sort_order;
+        }
+        self.record(AuditEvent::QuestionUpdated(id));
+
+        Ok(id)
+    }
+
+    /// The edit distance and similarity of two lines, and whether loading would merge them
+    pub fn compare(&self, a: &str, b: &str) -> (usize, f64, bool) {
+        let question = Question::new(a.to_string());
+        let merged = (normalize(a) == normalize(b)
+            || !self.dedup.strict_only && question.matches(b, &self.dedup))
+            && (!self.dedup.exact || a == b);
+
+        (
+            question.distance(b, self.dedup.algorithm),
+            question.similarity_with(b, self.dedup.algorithm),
+            merged,
+        )
+    }
+
+    /// The stored questions in the order sequential selection posts them
+    pub fn questions_in_order(&self) -> Vec<&Question> {
+        let mut questions = self.state.questions.iter().collect::<Vec<&Question>>();
+        questions.sort_by_key(|q| (q.sort_order, q.added_at));
+
+        questions
+    }
+
+    /// The unanswered questions as a plain source, in the order --sequential posts them.
+    /// Categories become `## Name` sections when those are read.
+    pub fn export_plain(&self) -> String {
+        let mut lines = vec![];
+        let mut section = None;
+        for question in self
+            .questions_in_order()
+            .into_iter()
+            .filter(|q| !q.answered)
+        {
+            if self.plain_options.section_categories && question.category != section {
+                section = question.category.clone();
+                lines.push(format!("## {}", section.as_deref().unwrap_or_default()));
+            }
+
+            let entry = Entry {
+                text: question.text.clone(),
+                author: question.author.clone(),
+                category: question.category.clone(),
+                poll_options: question.poll_options.clone(),
+                pinned: question.pinned,
+                variants: question.variants.clone(),
+                ..Default::default()
+            };
+            lines.push(entry.to_plain_line());
+        }
+
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// The id of the only question whose id starts with `prefix`
+    fn find_by_prefix(&self, prefix: &str) -> Result<Uuid, Err> {
+        let matching = self
+            .state
+            .questions
+            .iter()
+            .filter(|q| q.id.to_string().starts_with(prefix))
+            .map(|q| q.id)
+            .collect::<Vec<Uuid>>();
+        match matching[..] {
+            [id] => Ok(id),
+            [] => Err(format!("No question with an id starting with {}", prefix).into()),
+            _ => Err(format!(
+                "{} questions have an id starting with {}",
+                matching.len(),
+                prefix
+            )
+            .into()),
+        }
+    }
+
+    /// Stops or restarts the automatic posts, returning false if that was already the case
+    pub fn set_paused(&mut self, paused: bool) -> bool {
+        if self.state.paused == paused {
+            return false;
+        }
+
+        self.state.paused = paused;
+        self.record(match paused {
+            true => AuditEvent::PostingPaused,
+            false => AuditEvent::PostingResumed,
+        });
+
+        true
+    }
+
+    /// Removes the question whose id starts with `prefix`, remembering its text so that
+    /// loading the sources doesn't add it back
+    pub fn delete(&mut self, prefix: &str) -> Result<Uuid, Err> {
+        let id = self.find_by_prefix(prefix)?;
+        if let Some(index) = self.state.questions.iter().position(|q| q.id == id) {
+            let question = self.state.questions.remove(index);
+            let key = normalize(&question.text);
+            self.norm_index.remove(&key);
+            self.state.deleted.push(key);
+        }
+        self.state.bag.retain(|bag_id| *bag_id != id);
+        self.record(AuditEvent::QuestionDeleted(id));
+
+        Ok(id)
+    }
+
+    /// Merges plain lines like a source, returning how many were added, how many updated a
+    /// stored question and how many were skipped as unchanged, deleted or over the caps
+    pub fn import(&mut self, raw: &str) -> Result<(usize, usize, usize), Err> {
+        let entries = SourceFormat::Plain.parse(raw, &self.plain_options)?;
+        let total = entries.len();
+        let merged = self.merge(self.limit(entries));
+
+        Ok((
+            merged.added,
+            merged.updated,
+            total - merged.added - merged.updated,
+        ))
+    }
+
+    pub fn questions_matching_pattern(&self, pattern: &str) -> Result<Vec<&Question>, Err> {
+        let regex = Regex::new(pattern)?;
+
+        Ok(self
+            .state
+            .questions
+            .iter()
+            .filter(|q| regex.is_match(&q.text))
+            .collect())
+    }
+
+    /// Posts a sample question so the embed can be checked in Discord, without storing it
+    pub async fn send_test_embed(&self) -> Result<Value, Err> {
+        let question = Question::from(Entry {
+            text: "This is a test question — please ignore!".to_string(),
+            category: Some("Test".to_string()),
+            ..Default::default()
+        });
+
+        let ctx = self
+            .hook
+            .render_context(Some((1, 1)), self.template.show_post_count.then_some(1));
+        let embed = self.template.render(&question, &ctx);
+        self.hook.send(embed.clone()).await?;
+
+        Ok(embed)
+    }
+}
+
+/// An inclusive range of dates, written as `YYYY-MM-DD/YYYY-MM-DD`
+#[derive(Debug, Clone, Copy)]
+pub struct DateRange {
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+impl DateRange {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+}
+
+impl FromStr for DateRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Expected a range like 2021-12-24/2021-12-26, got {}", s))?;
+        let start = start
+            .trim()
+            .parse::<NaiveDate>()
+            .map_err(|e| e.to_string())?;
+        let end = end.trim().parse::<NaiveDate>().map_err(|e| e.to_string())?;
+
+        if end < start {
+            return Err(format!("Range {} ends before it starts", s));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// How many days have to pass between posts from a category, written as `category=days`
+#[derive(Debug, Clone)]
+pub struct CategoryCooldown {
+    category: String,
+    days: i64,
+}
+
+impl FromStr for CategoryCooldown {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (category, days) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected a cooldown like trivia=3, got {}", s))?;
+        let days = days.trim().parse::<i64>().map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            category: category.trim().to_string(),
+            days,
+        })
+    }
+}
+
+/// Parses a cron expression, either with seconds as the cron crate expects or in the
+/// classic five field form where days of the week count from 0 for Sunday
+pub fn parse_schedule(s: &str) -> Result<Schedule, String> {
+    let fields = s.split_whitespace().collect::<Vec<&str>>();
+    let expression = match fields[..] {
+        [minute, hour, day, month, weekday] => format!(
+            "0 {} {} {} {} {}",
+            minute,
+            hour,
+            day,
+            month,
+            classic_weekdays(weekday)
+        ),
+        _ => s.to_string(),
+    };
+
+    Schedule::from_str(&expression).map_err(|e| format!("Invalid cron expression {}: {}", s, e))
+}
+
+/// Translates numeric days of the week from the classic 0-7 to the cron crate's 1-7, both
+/// starting on Sunday. Numeric parts are spelled out day by day, so a range ending on
+/// Sunday like `5-7` doesn't turn into the descending `6-1`. Names and `*` are left alone.
+fn classic_weekdays(field: &str) -> String {
+    field
+        .split(',')
+        .map(|part| match classic_days(part) {
+            Some(days) => days
+                .iter()
+                .map(|day| day.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            None => part.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// The cron crate's days for a part like `5`, `1-5` or `1-5/2`, None when it isn't numeric
+/// or out of range, for the cron crate to reject
+fn classic_days(part: &str) -> Option<Vec<u8>> {
+    let (range, step) = match part.split_once('/') {
+        Some((range, step)) => (range, step.parse::<usize>().ok().filter(|step| *step > 0)?),
+        None => (part, 1),
+    };
+    let (first, last) = match range.split_once('-') {
+        Some((first, last)) => (first.parse::<u8>().ok()?, last.parse::<u8>().ok()?),
+        None => {
+            let day = range.parse::<u8>().ok()?;
+            (day, if step > 1 { 7 } else { day })
+        }
+    };
+    if first > last || last > 7 {
+        return None;
+    }
+
+    let mut days = (first..=last)
+        .step_by(step)
+        .map(|day| day % 7 + 1)
+        .collect::<Vec<u8>>();
+    days.sort_unstable();
+    days.dedup();
+    Some(days)
+}
+
+impl fmt::Debug for Bot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bot {{ urls: {:?} }}", self.urls)
+    }
+}
+
+impl Question {
+    pub fn new(text: String) -> Question {
+        Question {
+            id: Uuid::new_v4(),
+            text,
+            answered: false,
+            author: None,
+            category: None,
+            poll_options: vec![],
+            answered_at: None,
+            pinned: false,
+            added_at: Utc::now(),
+            embed_image_url: None,
+            variants: vec![],
+            sort_order: 0,
+            priority: false,
+            channel_id: None,
+            message_id: None,
+            reaction_count: None,
+            scheduled_for: None,
+            custom_fields: vec![],
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn is_eligible(&self) -> bool {
+        !self.answered || self.pinned
+    }
+
+    pub fn answered(&self) -> bool {
+        self.answered
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The text in the given language, 0 being the original. Falls back to the original
+    /// when the question wasn't translated into that language.
+    pub fn localized_text(&self, variant: usize) -> &str {
+        match variant {
+            0 => &self.text,
+            _ => self.variants.get(variant - 1).unwrap_or(&self.text),
+        }
+    }
+
+    pub fn answered_at(&self) -> Option<DateTime<Utc>> {
+        self.answered_at
+    }
+
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    pub fn embed_image_url(&self) -> Option<&str> {
+        self.embed_image_url.as_deref()
+    }
+
+    pub fn reaction_count(&self) -> Option<u32> {
+        self.reaction_count
+    }
+
+    pub fn sort_order(&self) -> i64 {
+        self.sort_order
+    }
+
+    pub fn custom_fields(&self) -> &[(String, String, bool)] {
+        &self.custom_fields
+    }
+
+    fn differs_from(&self, entry: &Entry) -> bool {
+        self.text != entry.text
+            || self.pinned != entry.pinned
+            || entry.author.is_some() && self.author != entry.author
+            || entry.category.is_some() && self.category != entry.category
+            || !entry.poll_options.is_empty() && self.poll_options != entry.poll_options
+            || !entry.variants.is_empty() && self.variants != entry.variants
+            || !entry.custom_fields.is_empty() && self.custom_fields != entry.custom_fields
+    }
+
+    /// Takes what the entry sets, a field it leaves unset keeps its stored value
+    fn update(&mut self, entry: Entry) {
+        self.text = entry.text;
+        self.pinned = entry.pinned;
+        if entry.author.is_some() {
+            self.author = entry.author;
+        }
+        if entry.category.is_some() {
+            self.category = entry.category;
+        }
+        if !entry.poll_options.is_empty() {
+            self.poll_options = entry.poll_options;
+        }
+        if !entry.variants.is_empty() {
+            self.variants = entry.variants;
+        }
+        if !entry.custom_fields.is_empty() {
+            self.custom_fields = entry.custom_fields;
+        }
+    }
+
+    fn matches(&self, other: &str, dedup: &DedupOptions) -> bool {
+        if dedup.exact {
+            return self.text == other;
+        }
+
+        match dedup.algorithm {
+            DeduplicationAlgorithm::Exact => normalize(&self.text) == normalize(other),
+            DeduplicationAlgorithm::JaroWinkler => {
+                self.similarity_with(other, dedup.algorithm) >= dedup.similarity_threshold
+            }
+            algorithm => {
+                let distance = self.distance(other, algorithm);
+                distance < dedup.threshold
+                    || similarity(distance, &self.text, other) >= dedup.similarity_threshold
+            }
+        }
+    }
+
+    /// How similar `other` is to the text, 1.0 for identical and 0.0 for completely different
+    pub fn similarity_with(&self, other: &str, algorithm: DeduplicationAlgorithm) -> f64 {
+        match algorithm {
+            DeduplicationAlgorithm::Exact => match normalize(&self.text) == normalize(other) {
+                true => 1.0,
+                false => 0.0,
+            },
+            DeduplicationAlgorithm::JaroWinkler => strsim::jaro_winkler(&self.text, other),
+            algorithm => similarity(self.distance(other, algorithm), &self.text, other),
+        }
+    }
+
+    fn distance(&self, other: &str, algorithm: DeduplicationAlgorithm) -> usize {
+        match algorithm {
+            DeduplicationAlgorithm::Levenshtein => {
+                strsim::generic_levenshtein(&graphemes(&self.text), &graphemes(other))
+            }
+            _ => self.damerau_levenshtein(other),
+        }
+    }
+
+    fn damerau_levenshtein(&self, other: &str) -> usize {
+        if self.text == other {
+            return 0;
+        }
+
+        // Indexed up front, walking the strings for every cell made long lines take forever.
+        // Compared by grapheme, so an emoji made of several chars is a single edit.
+        let a = graphemes(&self.text);
+        let b = graphemes(other);
+        if a.is_empty() || b.is_empty() {
+            return a.len().max(b.len());
+        }
+
+        let mut matrix = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 1..=a.len() {
+            matrix[i][0] = i;
+            for j in 1..=b.len() {
+                let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                if i == 1 {
+                    matrix[0][j] = j;
+                }
+
+                let vals = [
+                    matrix[i - 1][j] + 1,
+                    matrix[i][j - 1] + 1,
+                    matrix[i - 1][j - 1] + cost,
+                ];
+                matrix[i][j] = *vals.iter().min().unwrap();
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    matrix[i][j] = std::cmp::min(matrix[i][j], matrix[i - 2][j - 2] + cost);
+                }
+            }
+        }
+
+        matrix[a.len()][b.len()]
+    }
+}
+
+impl Default for DedupOptions {
+    fn default() -> Self {
+        Self {
+            exact: false,
+            threshold: 4,
+            similarity_threshold: 0.85,
+            strict_only: false,
+            algorithm: DeduplicationAlgorithm::DamerauLevenshtein,
+        }
+    }
+}
+
+/// Lowercases text and collapses whitespace, so trivially different lines compare equal
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn graphemes(text: &str) -> Vec<&str> {
+    text.graphemes(true).collect()
+}
+
+fn similarity(distance: usize, a: &str, b: &str) -> f64 {
+    let length = a.graphemes(true).count().max(b.graphemes(true).count());
+    if length == 0 {
+        return 1.0;
+    }
+
+    1.0 - (distance.min(length) as f64 / length as f64)
+}
+
+/// Looks up a random landscape photo for `query`, returning its URL
+async fn unsplash_image(key: &str, query: &str) -> Result<String, Err> {
+    let url = format!(
+        "https://api.unsplash.com/photos/random?query={}&orientation=landscape",
+        url::form_urlencoded::byte_serialize(query.as_bytes()).collect::<String>()
+    );
+    let request = isahc::Request::get(url)
+        .header("Authorization", format!("Client-ID {}", key))
+        .header("Accept-Version", "v1")
+        .body(())?;
+
+    let mut response = isahc::send_async(request).await?;
+    if !response.status().is_success() {
+        return Err(format!("Unsplash returned {}", response.status()).into());
+    }
+
+    let photo = serde_json::from_str::<Value>(&response.text().await?)?;
+    photo["urls"]["regular"]
+        .as_str()
+        .map(|url| url.to_string())
+        .ok_or_else(|| "Unsplash response has no urls.regular".into())
+}
+
+impl From<Entry> for Question {
+    fn from(entry: Entry) -> Self {
+        let mut question = Question::new(entry.text.clone());
+        question.update(entry);
+        question
+    }
+}
+
+impl Webhook {
+    pub fn new(id: u64, token: String) -> Self {
+        Self {
+            id,
+            token,
+            footer_timezone: Tz::UTC,
+            create_thread: false,
+            bot_token: None,
+            http_policy: HttpPolicy::default(),
+            forum: false,
+            forum_tags: vec![],
+            poll_duration_hours: 24,
+            edit_pinned: None,
+            rate_limit_delay: time::Duration::ZERO,
+            last_sent: Arc::default(),
+            api: Arc::new(SerenityApi),
+        }
+    }
+
+    /// Reads the id and token from a URL like `https://discord.com/api/webhooks/<id>/<token>`
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid webhook URL: {}", url);
+        let parsed = url::Url::parse(url).map_err(|_| invalid())?;
+        let mut segments = parsed.path_segments().ok_or_else(invalid)?;
+        match (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) {
+            (Some("api"), Some("webhooks"), Some(id), Some(token)) if !token.is_empty() => {
+                let id = id.parse().map_err(|_| invalid())?;
+                Ok(Self::new(id, token.to_string()))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    pub async fn send_intro(
+        &self,
+        template: &EmbedTemplate,
+        category: Option<&str>,
+    ) -> Result<(), Err> {
+        self.send(template.render_intro(category)).await?;
+
+        Ok(())
+    }
+
+    pub async fn send_announcement(&self, template: &EmbedTemplate, text: &str) -> Result<(), Err> {
+        self.send(template.render_announcement(text)).await?;
+
+        Ok(())
+    }
+
+    /// Sends a recap over as many messages as it takes to stay within the field limit
+    pub async fn send_monthly_recap(
+        &self,
+        template: &EmbedTemplate,
+        month: NaiveDate,
+        questions: &[&Question],
+    ) -> Result<(), Err> {
+        let pages = questions
+            .chunks(MAX_EMBED_FIELDS)
+            .collect::<Vec<&[&Question]>>();
+        for (page, questions) in pages.iter().enumerate() {
+            self.send(template.render_monthly_recap(month, questions, page + 1, pages.len()))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn send_embed_from_template(
+        &self,
+        template: &EmbedTemplate,
+        question: &Question,
+        number: Option<(usize, usize)>,
+        post_count: Option<u64>,
+    ) -> Result<Option<PostedMessage>, Err> {
+        let ctx = self.render_context(number, post_count);
+
+        let embed = template.render(question, &ctx);
+        if let Some(message_id) = self.edit_pinned {
+            return Ok(Some(self.edit(message_id, embed).await?));
+        }
+
+        let message = self.send(embed).await?;
+        // The question is out either way, a missing thread isn't worth failing the post over
+        if let Some(message) = message {
+            if let Err(e) = self
+                .start_thread(message, question.localized_text(template.variant))
+                .await
+            {
+                warn!("Failed to start a thread for message {}: {}", message.id, e);
+            }
+        }
+
+        Ok(message)
+    }
+
+    fn render_context(
+        &self,
+        number: Option<(usize, usize)>,
+        post_count: Option<u64>,
+    ) -> RenderContext {
+        RenderContext {
+            now: Utc::now().with_timezone(&self.footer_timezone),
+            number,
+            post_count,
+        }
+    }
+
+    /// Sends an embed, returning the posted message
+    async fn send(&self, mut embed: Value) -> Result<Option<PostedMessage>, Err> {
+        fit_embed(&mut embed)?;
+
+        let name = forum_post_name(&embed);
+        self.execute(|w| {
+            w.username("Question of the day");
+            w.embeds(vec![embed]);
+            self.add_forum_post(w, &name);
+        })
+        .await
+    }
+
+    /// Posts what `build` sets up. The id and token are all Discord needs, so the webhook
+    /// itself isn't fetched first.
+    async fn execute(
+        &self,
+        build: impl FnOnce(&mut ExecuteWebhook),
+    ) -> Result<Option<PostedMessage>, Err> {
+        let mut w = ExecuteWebhook::default();
+        build(&mut w);
+        let map = serenity::utils::hashmap_to_json_map(w.0);
+
+        self.wait_for_turn().await?;
+        let message = self
+            .timeout_once(|| self.api.execute_webhook(self.id, &self.token, &map))
+            .await??;
+        *self.last_sent.lock().map_err(|e| e.to_string())? = Some(time::Instant::now());
+
+        Ok(message)
+    }
+
+    /// Waits out `rate_limit_delay` after the last message, only a message following another
+    /// one has to wait
+    async fn wait_for_turn(&self) -> Result<(), Err> {
+        let last_sent = *self.last_sent.lock().map_err(|e| e.to_string())?;
+        if let Some(last_sent) = last_sent {
+            time::sleep_until(last_sent + self.rate_limit_delay).await;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the id and token belong to a webhook
+    pub async fn test_connectivity(&self) -> Result<(), Err> {
+        self.timeout(|| self.api.get_webhook(self.id, &self.token))
+            .await??;
+
+        Ok(())
+    }
+
+    /// Replaces the embed of a message the webhook posted earlier
+    async fn edit(&self, message_id: u64, mut embed: Value) -> Result<PostedMessage, Err> {
+        fit_embed(&mut embed)?;
+
+        let mut map = Map::new();
+        map.insert("embeds".to_string(), json!([embed]));
+        let message = self
+            .timeout(|| {
+                self.api
+                    .edit_webhook_message(self.id, &self.token, message_id, &map)
+            })
+            .await??;
+
+        Ok(message)
+    }
+
+    async fn start_thread(&self, message: PostedMessage, text: &str) -> Result<(), Err> {
+        let token = match (&self.bot_token, self.create_thread) {
+            (Some(token), true) => token,
+            _ => return Ok(()),
+        };
+
+        let payload = thread_payload(text);
+        let thread = self
+            .timeout_once(|| self.api.create_public_thread(token, message, &payload))
+            .await??;
+        info!("Started thread {} for message {}", thread, message.id);
+
+        Ok(())
+    }
+
+    async fn send_poll(
+        &self,
+        question: &str,
+        options: &[String],
+    ) -> Result<Option<PostedMessage>, Err> {
+        self.execute(|w| {
+            w.username("Question of the day");
+            w.0.insert(
+                "poll",
+                poll_payload(question, options, self.poll_duration_hours),
+            );
+            self.add_forum_post(w, &thread_name(question));
+        })
+        .await
+    }
+
+    /// Adds up the reactions on a posted message, reading messages needs a bot token
+    async fn reaction_count(&self, message: PostedMessage) -> Result<u32, Err> {
+        let token = self
+            .bot_token
+            .as_ref()
+            .ok_or("Counting reactions needs a bot token")?;
+
+        self.timeout(|| self.api.reaction_count(token, message))
+            .await?
+    }
+
+    pub async fn send_leaderboard(
+        &self,
+        template: &EmbedTemplate,
+        questions: &[&Question],
+    ) -> Result<(), Err> {
+        self.send(template.render_leaderboard(questions)).await?;
+
+        Ok(())
+    }
+
+    /// Forum channels need a name for the post every message starts
+    fn add_forum_post(&self, w: &mut ExecuteWebhook, name: &str) {
+        if !self.forum {
+            return;
+        }
+
+        w.0.insert("thread_name", json!(name));
+        if !self.forum_tags.is_empty() {
+            // Discord wants snowflakes as strings
+            let tags = self
+                .forum_tags
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect::<Vec<String>>();
+            w.0.insert("applied_tags", json!(tags));
+        }
+    }
+
+    /// Makes a call to Discord under `http_policy`, for calls that are safe to repeat
+    async fn timeout<T, F, Fut>(&self, call: F) -> Result<T, TimedOut>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = T>,
+    {
+        self.http_policy.run("Discord request", call).await
+    }
+
+    /// Makes a call to Discord that is never retried, since one whose response got lost may
+    /// have gone through and would post twice
+    async fn timeout_once<T, F, Fut>(&self, call: F) -> Result<T, TimedOut>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = T>,
+    {
+        let policy = HttpPolicy {
+            retries: 0,
+            ..self.http_policy
+        };
+        policy.run("Discord request", call).await
+    }
+}
+
+/// Waits for the next request to post right away, forever without a control endpoint
+async fn next_manual_post(posts: &mut Option<mpsc::Receiver<PostRequest>>) -> Option<PostRequest> {
+    match posts {
+        Some(posts) => posts.recv().await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Builds the body of a start-thread-from-message request
+fn thread_payload(text: &str) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("name".to_string(), json!(thread_name(text)));
+    map.insert("auto_archive_duration".to_string(), json!(1440));
+    map
+}
+
+/// Cuts `text` to the 100 characters a thread name is limited to
+fn thread_name(text: &str) -> String {
+    if text.chars().count() > 100 {
+        format!("{}…", text.chars().take(99).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+/// Title of the forum post an embed is sent as, the question or else the embed title
+fn forum_post_name(embed: &Value) -> String {
+    let text = [&embed["description"], &embed["title"]]
+        .iter()
+        .filter_map(|value| value.as_str())
+        .map(|text| text.trim_end_matches('\u{200B}').trim())
+        .find(|text| !text.is_empty())
+        .unwrap_or("Question of the day");
+
+    thread_name(text)
+}
+
+/// Checks that a poll duration is within the 1 to 768 hours Discord accepts
+pub fn parse_poll_duration(s: &str) -> Result<u16, String> {
+    match s.parse::<u16>() {
+        Ok(hours @ 1..=768) => Ok(hours),
+        _ => Err(format!("Poll duration must be 1 to 768 hours, got {}", s)),
+    }
+}
+
+/// Builds the `poll` object of a webhook execute payload
+fn poll_payload(question: &str, options: &[String], duration_hours: u16) -> Value {
+    // Discord accepts at most 10 answers per poll
+    let answers = options
+        .iter()
+        .take(10)
+        .map(|option| json!({ "poll_media": { "text": option } }))
+        .collect::<Vec<Value>>();
+
+    json!({
+        "question": { "text": question },
+        "answers": answers,
+        "duration": duration_hours,
+        "allow_multiselect": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn bot() -> Bot {
+        let mut bot = Bot::new(vec![], Webhook::new(0, String::new()), NaiveTime::MIN);
+        bot.storage = Box::new(MemoryStorage::default());
+        bot
+    }
+
+    /// Serves every source from memory, failing for URLs it has no body for
+    struct MockFetcher(HashMap<String, String>);
+
+    #[async_trait::async_trait]
+    impl SourceFetcher for MockFetcher {
+        async fn fetch(&self, url: &str) -> Result<String, Err> {
+            self.0
+                .get(url)
+                .cloned()
+                .ok_or_else(|| format!("{} is unreachable", url).into())
+        }
+    }
+
+    /// Records every message posted, answering as `outcome` says
+    #[derive(Debug, Default)]
+    struct MockDiscord {
+        outcome: std::sync::Mutex<Outcome>,
+        sent: std::sync::Mutex<Vec<Map<String, Value>>>,
+        threads_fail: bool,
+        /// The webhook was deleted, so looking it up fails
+        webhook_gone: bool,
+    }
+
+    #[derive(Debug, Default, Clone, Copy)]
+    enum Outcome {
+        #[default]
+        Post,
+        /// The message goes out, but the response never comes back
+        Hang,
+        /// The message is turned away without being posted
+        Busy,
+    }
+
+    impl MockDiscord {
+        fn answering(outcome: Outcome) -> Arc<Self> {
+            let discord = Arc::new(Self::default());
+            discord.answer_with(outcome);
+            discord
+        }
+
+        fn answer_with(&self, outcome: Outcome) {
+            *self.outcome.lock().unwrap() = outcome;
+        }
+
+        fn sent(&self) -> Vec<Map<String, Value>> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DiscordApi for MockDiscord {
+        async fn execute_webhook(
+            &self,
+            _: u64,
+            _: &str,
+            payload: &Map<String, Value>,
+        ) -> Result<Option<PostedMessage>, Err> {
+            let outcome = *self.outcome.lock().unwrap();
+            if let Outcome::Busy = outcome {
+                return Err(Box::new(Unavailable("503 Service Unavailable".to_string())));
+            }
+
+            let message = {
+                let mut sent = self.sent.lock().unwrap();
+                sent.push(payload.clone());
+                PostedMessage {
+                    channel_id: 1,
+                    id: sent.len() as u64,
+                }
+            };
+
+            match outcome {
+                Outcome::Hang => futures::future::pending().await,
+                _ => Ok(Some(message)),
+            }
+        }
+
+        async fn edit_webhook_message(
+            &self,
+            _: u64,
+            _: &str,
+            message_id: u64,
+            _: &Map<String, Value>,
+        ) -> Result<PostedMessage, Err> {
+            Ok(PostedMessage {
+                channel_id: 1,
+                id: message_id,
+            })
+        }
+
+        async fn get_webhook(&self, _: u64, _: &str) -> Result<(), Err> {
+            match self.webhook_gone {
+                true => Err("Unknown Webhook".into()),
+                false => Ok(()),
+            }
+        }
+
+        async fn create_public_thread(
+            &self,
+            _: &str,
+            message: PostedMessage,
+            _: &Map<String, Value>,
+        ) -> Result<u64, Err> {
+            match self.threads_fail {
+                true => Err("Missing Permissions".into()),
+                false => Ok(message.id),
+            }
+        }
+
+        async fn reaction_count(&self, _: &str, _: PostedMessage) -> Result<u32, Err> {
+            Ok(0)
+        }
+    }
+
+    /// A webhook that posts into `discord`, timing out quickly
+    fn hook(discord: &Arc<MockDiscord>) -> Webhook {
+        let mut hook = Webhook::new(0, String::new());
+        hook.api = discord.clone();
+        hook.http_policy = HttpPolicy {
+            timeout: time::Duration::from_millis(20),
+            retries: 2,
+            backoff: time::Duration::from_millis(1),
+        };
+        hook
+    }
+
+    /// Serves one body for every URL, counting the fetches
+    #[derive(Default)]
+    struct CountingFetcher(std::sync::atomic::AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl SourceFetcher for CountingFetcher {
+        async fn fetch(&self, _: &str) -> Result<String, Err> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok("Sweet or savoury?".to_string())
+        }
+    }
+
+    fn bot_with_sources(sources: &[(&str, &str)]) -> Bot {
+        let mut bot = bot();
+        bot.urls = sources.iter().map(|(url, _)| url.to_string()).collect();
+        bot.fetcher = Arc::new(MockFetcher(
+            sources
+                .iter()
+                .map(|(url, body)| (url.to_string(), body.to_string()))
+                .collect(),
+        ));
+        bot
+    }
+
+    fn entries(raw: &str) -> Vec<Entry> {
+        SourceFormat::Plain
+            .parse(raw, &PlainOptions::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn exact_merge_keeps_case_variants_apart() {
+        let mut bot = bot();
+        bot.dedup.exact = true;
+        for _ in 0..3 {
+            bot.merge(entries("Hello there\nhello there"));
+        }
+
+        let texts: Vec<_> = bot
+            .state
+            .questions
+            .iter()
+            .map(|q| q.text.as_str())
+            .collect();
+        assert_eq!(texts, ["Hello there", "hello there"]);
+    }
+
+    /// Picks a batch the way `answer` does, marking every pick posted before the next one
+    fn pick_batch(bot: &mut Bot, size: usize) -> Vec<Uuid> {
+        let mut posted = HashSet::new();
+        let mut picks = vec![];
+        for _ in 0..size {
+            let Some(id) = bot.next_question_id(Utc::now(), &posted) else {
+                break;
+            };
+            bot.mark_posted(id, Utc::now());
+            posted.insert(id);
+            picks.push(id);
+        }
+        picks
+    }
+
+    #[test]
+    fn batch_posts_a_pinned_question_once() {
+        let mut bot = bot();
+        let mut pinned = Question::new("Still here?".to_string());
+        pinned.pinned = true;
+        let id = pinned.id;
+        bot.state.questions.push(pinned);
+
+        assert_eq!(pick_batch(&mut bot, 3), [id]);
+    }
+
+    #[test]
+    fn batch_with_repeats_allowed_has_no_duplicates() {
+        let mut bot = bot();
+        bot.selection_mode = SelectionMode::AllowRepeats;
+        bot.merge(entries(
+            "Favourite season?\nBest film this year?\nCoffee or tea?",
+        ));
+
+        let picks = pick_batch(&mut bot, 3);
+        let distinct: HashSet<_> = picks.iter().collect();
+        assert_eq!(picks.len(), 3);
+        assert_eq!(distinct.len(), 3);
+    }
+
+    fn at(date: &str, time: &str) -> DateTime<Utc> {
+        format!("{}T{}:00Z", date, time).parse().unwrap()
+    }
+
+    #[test]
+    fn dry_run_tick_skips_while_paused() {
+        let mut bot = bot();
+        bot.merge(entries("Anyone there?"));
+        bot.state.paused = true;
+        let tick = bot.dry_run_tick(at("2024-03-01", "00:00"), "").unwrap();
+        assert_eq!(tick.question_posted, None);
+
+        bot.state.paused = false;
+        let tick = bot.dry_run_tick(at("2024-03-01", "00:00"), "").unwrap();
+        assert!(tick.question_posted.is_some());
+    }
+
+    #[test]
+    fn dry_run_tick_applies_the_uniqueness_window_at_its_own_time() {
+        let mut bot = bot();
+        bot.uniqueness_window_days = Some(7);
+        let mut pinned = Question::new("Same as last week?".to_string());
+        pinned.pinned = true;
+        pinned.answered = true;
+        pinned.answered_at = Some(at("2024-01-01", "00:00"));
+        let id = pinned.id;
+        bot.state.questions.push(pinned);
+
+        let tick = bot.dry_run_tick(at("2024-01-03", "00:00"), "").unwrap();
+        assert_eq!(tick.question_posted, None);
+        let tick = bot.dry_run_tick(at("2024-02-01", "00:00"), "").unwrap();
+        assert_eq!(tick.question_posted, Some(id));
+    }
+
+    #[test]
+    fn peek_previews_every_pick_of_a_cycle() {
+        for mode in [SelectionMode::Sequential, SelectionMode::ShuffledCycle] {
+            let mut bot = bot();
+            bot.selection_mode = mode;
+            bot.merge(entries(
+                "Favourite season?\nBest film this year?\nCoffee or tea?\nFirst concert you went to?",
+            ));
+
+            let now = Utc::now();
+            for _ in 0..4 {
+                let peeked = bot.peek_next_question(now).map(|q| q.id);
+                let picked = bot.next_question_id(now, &HashSet::new()).unwrap();
+                // Only the first draw of a cycle has to reshuffle the bag
+                if peeked.is_some() || mode == SelectionMode::Sequential {
+                    assert_eq!(peeked, Some(picked), "{:?}", mode);
+                }
+                bot.state.bag.retain(|id| *id != picked);
+                bot.mark_posted(picked, now);
+            }
+        }
+    }
+
+    #[test]
+    fn peek_skips_a_scheduled_question_that_was_answered() {
+        let mut bot = bot();
+        let now = Utc::now();
+        let mut scheduled = Question::new("Weekly again?".to_string());
+        scheduled.pinned = true;
+        scheduled.answered = true;
+        scheduled.scheduled_for = Some(now.date_naive());
+        let mut prioritized = Question::new("Jump the queue?".to_string());
+        prioritized.priority = true;
+        let id = prioritized.id;
+        bot.state.questions.extend([scheduled, prioritized]);
+
+        assert_eq!(bot.peek_next_question(now).map(|q| q.id), Some(id));
+        assert_eq!(bot.next_question_id(now, &HashSet::new()), Some(id));
+    }
+
+    #[test]
+    fn post_after_an_intro_uses_the_announced_question() {
+        let mut bot = bot();
+        bot.selection_mode = SelectionMode::Sequential;
+        bot.merge(entries("Favourite season?\nBest film this year?"));
+        let announced = bot.questions_in_order()[1].id;
+        bot.state.intro_question = Some(announced);
+
+        let now = Utc::now();
+        assert_eq!(bot.peek_next_question(now).map(|q| q.id), Some(announced));
+        assert_eq!(bot.next_question_id(now, &HashSet::new()), Some(announced));
+
+        bot.mark_posted(announced, now);
+        assert_eq!(bot.state.intro_question, None);
+        assert_ne!(bot.next_question_id(now, &HashSet::new()), Some(announced));
+    }
+
+    /// Weekdays of the next week of fire times, starting on a Monday
+    fn weekdays(cron: &str) -> Vec<Weekday> {
+        let monday = at("2024-01-01", "00:00");
+        let mut days = parse_schedule(cron)
+            .unwrap()
+            .after(&monday)
+            .take_while(|fire| *fire < monday + Duration::days(7))
+            .map(|fire| fire.weekday())
+            .collect::<Vec<Weekday>>();
+        days.sort_by_key(|day| day.num_days_from_sunday());
+        days
+    }
+
+    #[test]
+    fn classic_weekday_ranges() {
+        use Weekday::*;
+        assert_eq!(weekdays("0 9 * * 0-6"), [Sun, Mon, Tue, Wed, Thu, Fri, Sat]);
+        assert_eq!(weekdays("0 9 * * 5-7"), [Sun, Fri, Sat]);
+        assert_eq!(weekdays("0 9 * * 1-5"), [Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(weekdays("0 9 * * 0,7"), [Sun]);
+        assert_eq!(weekdays("0 9 * * 1-5/2"), [Mon, Wed, Fri]);
+        assert_eq!(weekdays("0 9 * * MON-FRI"), [Mon, Tue, Wed, Thu, Fri]);
+    }
+
+    #[test]
+    fn manual_post_leaves_the_scheduled_one_due() {
+        let mut bot = bot();
+        bot.merge(entries("Favourite season?\nBest film this year?"));
+        let midnight = at("2024-03-01", "00:00");
+
+        let manual = bot.next_question_id(midnight, &HashSet::new()).unwrap();
+        bot.mark_posted(manual, midnight);
+        assert!(bot.is_due(midnight));
+
+        let tick = bot.dry_run_tick(midnight, "").unwrap();
+        assert!(tick.question_posted.is_some_and(|id| id != manual));
+        assert!(!bot.is_due(midnight));
+    }
+
+    #[tokio::test]
+    async fn load_merges_every_source() {
+        for concurrent_sources in [false, true] {
+            let mut bot = bot_with_sources(&[
+                (
+                    "https://example.com/b",
+                    "Mountains or sea?\nFavourite season?",
+                ),
+                (
+                    "https://example.com/a",
+                    "Favourite season?\nBest film this year?",
+                ),
+            ]);
+            bot.concurrent_sources = concurrent_sources;
+
+            let listed = bot.load().await.unwrap().unwrap();
+            assert_eq!(bot.state.questions.len(), 3);
+            assert_eq!(listed.len(), 3);
+            // Sources are merged in URL order, however they're fetched
+            assert_eq!(bot.state.questions[0].text, "Favourite season?");
+        }
+    }
+
+    #[tokio::test]
+    async fn load_with_an_empty_source_lists_nothing_to_prune() {
+        let mut bot = bot_with_sources(&[
+            ("https://example.com/a", "Favourite season?"),
+            ("https://example.com/b", ""),
+        ]);
+
+        assert_eq!(bot.load().await.unwrap(), None);
+        assert_eq!(bot.state.questions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn load_fails_when_a_source_does() {
+        let mut bot = bot_with_sources(&[("https://example.com/a", "Favourite season?")]);
+        bot.urls.push("https://example.com/gone".to_string());
+
+        let e = bot.load().await.unwrap_err();
+        assert_eq!(e.to_string(), "https://example.com/gone is unreachable");
+        assert!(bot.state.questions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn validate_reports_a_problematic_source() {
+        let source = "Favourite season?\n\nfavourite  season?\nWhat would you do with a whole free week?\nBest film this year?";
+        let mut bot = bot_with_sources(&[("https://example.com/q", source)]);
+        bot.max_source_line_length = 30;
+        bot.max_source_lines = 3;
+
+        let reports = bot.validate_sources().await.unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].questions, 2);
+        assert_eq!(
+            reports[0].problems,
+            [
+                "line 2 is blank",
+                "has 4 questions, only the first 3 are read",
+                "\"What would you do with a whole free week?\" is 41 characters long and skipped, the limit is 30",
+                "\"favourite  season?\" duplicates \"Favourite season?\"",
+            ]
+        );
+        assert!(bot.state.questions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_a_message_following_another_waits() {
+        let mut hook = Webhook::new(0, String::new());
+        hook.rate_limit_delay = time::Duration::from_millis(200);
+
+        let first = time::Instant::now();
+        hook.wait_for_turn().await.unwrap();
+        assert!(first.elapsed() < hook.rate_limit_delay);
+
+        // Clones pace together, they post through the same webhook
+        *hook.clone().last_sent.lock().unwrap() = Some(time::Instant::now());
+        let next = time::Instant::now();
+        hook.wait_for_turn().await.unwrap();
+        assert!(next.elapsed() >= time::Duration::from_millis(150));
+    }
+
+    #[test]
+    fn import_applies_the_source_caps() {
+        let mut bot = bot();
+        bot.max_source_line_length = 20;
+        bot.merge(entries("Favourite season?"));
+
+        let imported =
+            "Favourite season?\nBest film this year?\nWhat would you do with a free week?";
+        assert_eq!(bot.import(imported).unwrap(), (1, 0, 2));
+        assert_eq!(bot.state.questions.len(), 2);
+    }
+
+    #[test]
+    fn import_counts_updates_apart_from_skips() {
+        let mut bot = bot();
+        bot.merge(entries("Favourite season?\nBest film this year?"));
+
+        let imported = "Favourite season? -- @Ana\nBest film this year?\nWhere to next?";
+        assert_eq!(bot.import(imported).unwrap(), (1, 1, 1));
+        assert_eq!(bot.state.questions[0].author.as_deref(), Some("Ana"));
+    }
+
+    #[tokio::test]
+    async fn a_send_that_timed_out_is_not_retried() {
+        let discord = MockDiscord::answering(Outcome::Hang);
+        let hook = hook(&discord);
+
+        let e = hook
+            .send_announcement(&EmbedTemplate::default(), "Hello")
+            .await
+            .unwrap_err();
+        assert!(e.is::<TimedOut>(), "{}", e);
+        assert_eq!(discord.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_post_that_timed_out_after_delivery_is_not_sent_again() {
+        let discord = MockDiscord::answering(Outcome::Hang);
+        let mut bot = bot();
+        bot.hook = hook(&discord);
+        bot.merge(entries("Favourite season?"));
+
+        let e = bot
+            .answer_one(Utc::now(), &HashSet::new())
+            .await
+            .unwrap_err();
+        assert!(e.is::<TimedOut>(), "{}", e);
+
+        discord.answer_with(Outcome::Post);
+        bot.retry_unsent().await;
+        assert_eq!(discord.sent().len(), 1);
+        assert!(bot.state.questions[0].answered);
+    }
+
+    #[tokio::test]
+    async fn a_post_discord_turned_away_is_sent_again() {
+        let discord = MockDiscord::answering(Outcome::Busy);
+        let mut bot = bot();
+        bot.hook = hook(&discord);
+        bot.merge(entries("Favourite season?"));
+
+        let e = bot
+            .answer_one(Utc::now(), &HashSet::new())
+            .await
+            .unwrap_err();
+        assert!(e.is::<Unavailable>(), "{}", e);
+        bot.retry_unsent().await;
+        assert!(discord.sent().is_empty(), "still turned away");
+
+        discord.answer_with(Outcome::Post);
+        bot.retry_unsent().await;
+        bot.retry_unsent().await;
+        assert_eq!(discord.sent().len(), 1);
+        assert_eq!(bot.state.questions[0].message_id, Some(1));
+    }
+
+    #[tokio::test]
+    async fn a_failed_thread_keeps_the_post() {
+        let discord = Arc::new(MockDiscord {
+            threads_fail: true,
+            ..MockDiscord::default()
+        });
+        let mut bot = bot();
+        bot.hook = hook(&discord);
+        bot.hook.create_thread = true;
+        bot.hook.bot_token = Some("bot".to_string());
+        bot.merge(entries("Favourite season?"));
+
+        let id = bot.answer_one(Utc::now(), &HashSet::new()).await.unwrap();
+        assert_eq!(id, Some(bot.state.questions[0].id));
+        assert_eq!(bot.state.questions[0].channel_id, Some(1));
+        assert_eq!(bot.state.questions[0].message_id, Some(1));
+    }
+
+    #[test]
+    fn submitting_a_near_duplicate_keeps_the_stored_question() {
+        let mut bot = bot();
+        bot.merge(entries("Cats or dogs? | Cats | Dogs -- @Ana"));
+        bot.state.questions[0].category = Some("Pets".to_string());
+        let stored = bot.state.questions[0].id;
+
+        let submitted = bot.submit("Cats or dogs??".to_string(), None).unwrap();
+        assert_ne!(submitted, stored);
+        let question = &bot.state.questions[0];
+        assert_eq!(question.text, "Cats or dogs?");
+        assert_eq!(question.author.as_deref(), Some("Ana"));
+        assert_eq!(question.category.as_deref(), Some("Pets"));
+        assert_eq!(question.poll_options, ["Cats", "Dogs"]);
+
+        let same = bot.submit("cats or  dogs?".to_string(), Some("Animals".to_string()));
+        assert_eq!(same, Some(stored));
+        let question = &bot.state.questions[0];
+        assert_eq!(question.category.as_deref(), Some("Animals"));
+        assert_eq!(question.author.as_deref(), Some("Ana"));
+        assert_eq!(bot.state.questions.len(), 2);
+    }
+
+    #[test]
+    fn scheduling_a_stored_question_only_sets_its_date() {
+        let mut bot = bot();
+        bot.merge(entries(
+            "Cats or dogs? | Cats | Dogs -- @Ana\nTea or coffee?",
+        ));
+        bot.state.questions[0].category = Some("Pets".to_string());
+        bot.state.questions[0].answered = true;
+        let stored = bot.state.questions[0].id;
+        let date = at("2024-06-01", "00:00").date_naive();
+
+        let near = bot.schedule_question(date, "Cats or dogs??".to_string(), None);
+        assert_ne!(near, Some(stored));
+        assert_eq!(bot.state.questions[0].scheduled_for, None);
+
+        let id = bot.schedule_question(date, "cats or dogs?".to_string(), Some("Fun".to_string()));
+        assert_eq!(id, Some(stored));
+        let question = &bot.state.questions[0];
+        assert_eq!(question.scheduled_for, Some(date));
+        assert!(!question.answered);
+        assert_eq!(question.author.as_deref(), Some("Ana"));
+        assert_eq!(question.category.as_deref(), Some("Pets"));
+        assert_eq!(question.poll_options, ["Cats", "Dogs"]);
+    }
+
+    #[test]
+    fn a_source_line_without_a_field_keeps_the_stored_one() {
+        let mut bot = bot();
+        bot.merge(entries("Cats or dogs? | Cats | Dogs -- @Ana"));
+        bot.state.questions[0].category = Some("Pets".to_string());
+
+        let merged = bot.merge(entries("Cats  or dogs?!"));
+        assert_eq!(merged.updated, 1);
+        let question = &bot.state.questions[0];
+        assert_eq!(question.text, "Cats  or dogs?!");
+        assert_eq!(question.author.as_deref(), Some("Ana"));
+        assert_eq!(question.category.as_deref(), Some("Pets"));
+        assert_eq!(question.poll_options, ["Cats", "Dogs"]);
+
+        bot.merge(entries("Cats  or dogs?! -- @Bo"));
+        assert_eq!(bot.state.questions[0].author.as_deref(), Some("Bo"));
+        assert_eq!(bot.merge(entries("Cats  or dogs?!")).updated, 0);
+    }
+
+    #[test]
+    fn pruning_keeps_the_streak() {
+        let mut bot = bot();
+        bot.answered_questions_keep = 1;
+        let days = ["2024-03-01", "2024-03-02", "2024-03-03"];
+        for (day, source) in days.iter().zip(["Monday?", "Tuesday?", "Wednesday?"]) {
+            let tick = bot.dry_run_tick(at(day, "00:00"), source).unwrap();
+            assert!(tick.question_posted.is_some());
+        }
+        assert_eq!(bot.state.questions.len(), 1);
+
+        let today = at("2024-03-03", "00:00").date_naive();
+        assert_eq!(bot.streak(today), 3);
+        assert_eq!(bot.streak(today.succ_opt().unwrap()), 3);
+        assert_eq!(bot.streak(today + Duration::days(2)), 0);
+    }
+
+    #[test]
+    fn a_repost_the_same_day_or_an_older_one_adds_nothing_to_the_streak() {
+        let mut bot = bot();
+        bot.merge(entries("Tea?\nCoffee?\nJuice?"));
+        let ids = bot
+            .state
+            .questions
+            .iter()
+            .map(|q| q.id)
+            .collect::<Vec<Uuid>>();
+
+        bot.mark_posted(ids[0], at("2024-03-02", "00:00"));
+        bot.mark_posted(ids[1], at("2024-03-02", "12:00"));
+        bot.mark_posted(ids[2], at("2024-03-01", "00:00"));
+
+        assert_eq!(bot.streak(at("2024-03-02", "00:00").date_naive()), 1);
+        assert_eq!(bot.streak(at("2024-03-01", "00:00").date_naive()), 0);
+    }
+
+    #[tokio::test]
+    async fn an_excluded_day_carries_the_streak_and_old_files_rebuild_it() {
+        let mut bot = bot();
+        bot.exclude_dates = vec![at("2024-03-02", "00:00").date_naive()];
+        bot.merge(entries("Tea?\nCoffee?"));
+        bot.state.questions[0].answered_at = Some(at("2024-03-01", "00:00"));
+        bot.state.questions[1].answered_at = Some(at("2024-03-03", "00:00"));
+        bot.save().await.unwrap();
+
+        bot.restore().await.unwrap();
+        assert_eq!(bot.streak(at("2024-03-03", "00:00").date_naive()), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_loop_posts_a_due_question_and_saves_it() {
+        let discord = MockDiscord::answering(Outcome::Post);
+        let mut bot = bot_with_sources(&[("https://example.com/q.txt", "Sunrise or sunset?")]);
+        bot.hook = hook(&discord);
+        bot.schedule = Some(parse_schedule("* * * * *").unwrap());
+
+        // Paused time runs through the ticks at once, the schedule fires by the wall clock
+        let run = time::timeout(TICK * 5, bot.start()).await;
+        assert!(run.is_err(), "the loop only returns on errors: {:?}", run);
+
+        bot.restore().await.unwrap();
+        let question = &bot.state.questions[0];
+        assert_eq!(question.text, "Sunrise or sunset?");
+        assert!(question.answered);
+        assert!(question.message_id.is_some());
+        assert!(!discord.sent().is_empty());
+        assert_eq!(bot.state.post_count as usize, discord.sent().len());
+    }
+
+    #[test]
+    fn the_summary_covers_the_week_before_reactions_are_counted() {
+        let mut bot = bot();
+        let posts = [
+            ("2024-03-03", "11:59", 9),
+            ("2024-03-03", "12:00", 1),
+            ("2024-03-10", "11:59", 2),
+            ("2024-03-10", "12:00", 8),
+            ("2024-03-05", "12:00", 3),
+            ("2024-03-06", "12:00", 4),
+            ("2024-03-07", "12:00", 0),
+        ];
+        for (i, (day, time, reactions)) in posts.into_iter().enumerate() {
+            let mut question = Question::new(format!("Question {}?", i));
+            question.answered = true;
+            question.answered_at = Some(at(day, time));
+            question.reaction_count = Some(reactions);
+            bot.state.questions.push(question);
+        }
+
+        // A Monday noon, and reactions are counted a day after the post
+        let top = bot.summary_questions(at("2024-03-11", "12:00"));
+        let texts = top.iter().map(|q| q.text.as_str()).collect::<Vec<&str>>();
+        assert_eq!(texts, ["Question 5?", "Question 4?", "Question 2?"]);
+    }
+
+    #[test]
+    fn eligibility_counts_every_reason_apart() {
+        let mut bot = bot();
+        bot.uniqueness_window_days = Some(7);
+        bot.merge(entries("Open?\nAnswered?\nLater?\nBatched?\nRecent?"));
+        let now = at("2024-03-10", "12:00");
+        let questions = &mut bot.state.questions;
+        questions[1].answered = true;
+        questions[2].scheduled_for = Some(at("2024-03-11", "00:00").date_naive());
+        questions[4].pinned = true;
+        questions[4].answered = true;
+        questions[4].answered_at = Some(at("2024-03-08", "12:00"));
+        let batch = HashSet::from([questions[3].id]);
+
+        assert_eq!(
+            bot.eligibility(now, &batch),
+            "1 of 5 questions are eligible, 1 were answered, 1 are scheduled later, 1 are in \
+             this batch and 1 were posted within the uniqueness window"
+        );
+    }
+
+    #[test]
+    fn a_repost_leaves_the_question_on_the_day_it_was_first_posted() {
+        let mut bot = bot();
+        bot.merge(entries("Tea? !pinned\nCoffee?"));
+        let (tea, coffee) = (bot.state.questions[0].id, bot.state.questions[1].id);
+        bot.mark_posted(tea, at("2024-03-01", "09:00"));
+        bot.mark_posted(coffee, at("2024-03-01", "18:00"));
+        bot.mark_posted(tea, at("2024-03-02", "09:00"));
+
+        let ids = |date: &str| {
+            bot.questions_posted_on(at(date, "00:00").date_naive())
+                .iter()
+                .map(|q| q.id)
+                .collect::<Vec<Uuid>>()
+        };
+        assert_eq!(ids("2024-03-01"), [tea, coffee]);
+        assert_eq!(ids("2024-03-02"), [tea]);
+        assert!(ids("2024-03-03").is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_question_whose_send_failed_is_not_picked_again_after_a_restart() {
+        for outcome in [Outcome::Busy, Outcome::Hang] {
+            let discord = MockDiscord::answering(outcome);
+            let mut crashed = bot();
+            crashed.hook = hook(&discord);
+            crashed.merge(entries("First one?\nSecond one?"));
+            crashed.save().await.unwrap();
+            let now = Utc::now();
+
+            assert!(crashed.answer(now).await.is_err());
+            let posted = crashed
+                .state
+                .questions
+                .iter()
+                .find(|q| q.answered)
+                .unwrap()
+                .id;
+
+            let mut restarted = bot();
+            restarted.storage =
+                std::mem::replace(&mut crashed.storage, Box::new(MemoryStorage::default()));
+            restarted.restore().await.unwrap();
+            let next = restarted.peek_next_question(now).unwrap();
+            assert_ne!(next.id, posted, "{:?}", outcome);
+            assert_eq!(restarted.pending_count(), 1);
+        }
+    }
+
+    #[test]
+    fn dry_run_ticks_announce_then_post_the_same_question() {
+        let mut bot = bot();
+        bot.post_at = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        bot.intro_offset = Duration::minutes(30);
+        let source = "Mountains or sea?\nSweet or savoury?\nEarly bird or night owl?";
+
+        let early = bot.dry_run_tick(at("2024-03-01", "11:29"), source).unwrap();
+        assert_eq!((early.intro_announced, early.question_posted), (None, None));
+
+        let intro = bot.dry_run_tick(at("2024-03-01", "11:30"), source).unwrap();
+        let announced = intro.intro_announced.unwrap();
+        assert_eq!(intro.question_posted, None);
+        let again = bot.dry_run_tick(at("2024-03-01", "11:30"), source).unwrap();
+        assert_eq!(again.intro_announced, None, "one intro a day");
+
+        let post = bot.dry_run_tick(at("2024-03-01", "12:00"), source).unwrap();
+        assert_eq!(post.intro_announced, None);
+        assert_eq!(post.question_posted, Some(announced));
+    }
+
+    #[test]
+    fn a_shuffled_cycle_posts_every_question_once_before_any_repeats() {
+        let mut bot = bot();
+        let source = "Favourite season?\nBest film this year?\nCoffee or tea?\nFirst concert?";
+        let mut day = at("2024-03-01", "00:00");
+        let mut posted = HashSet::new();
+        for _ in 0..4 {
+            let tick = bot.dry_run_tick(day, source).unwrap();
+            assert!(posted.insert(tick.question_posted.unwrap()));
+            day += Duration::days(1);
+        }
+        assert_eq!(posted.len(), 4);
+
+        // A question added after the others were posted still comes up, none of them repeat
+        let tick = bot
+            .dry_run_tick(day, &format!("{}\nDream job?", source))
+            .unwrap();
+        assert!(!posted.contains(&tick.question_posted.unwrap()));
+        let tick = bot.dry_run_tick(day + Duration::days(1), source).unwrap();
+        assert_eq!(tick.question_posted, None);
+    }
+
+    #[test]
+    fn pruning_keeps_the_most_recent_answered_and_every_unanswered_question() {
+        let mut bot = bot();
+        bot.answered_questions_keep = 3;
+        bot.merge(entries(
+            "Best breakfast?\nFavourite river?\nDream car?\nWorst habit?\nFirst pet?\nNext trip?\nHidden talent?",
+        ));
+        for (day, question) in bot.state.questions.iter_mut().take(5).enumerate() {
+            question.answered = true;
+            question.answered_at = Some(at("2024-03-01", "00:00") + Duration::days(day as i64));
+        }
+
+        assert_eq!(bot.prune(&HashSet::new()), 2);
+        let mut texts = bot
+            .state
+            .questions
+            .iter()
+            .map(|q| q.text.as_str())
+            .collect::<Vec<&str>>();
+        texts.sort();
+        assert_eq!(
+            texts,
+            [
+                "Dream car?",
+                "First pet?",
+                "Hidden talent?",
+                "Next trip?",
+                "Worst habit?",
+            ]
+        );
+        assert_eq!(bot.prune(&HashSet::new()), 0);
+    }
+
+    #[test]
+    fn history_includes_both_ends_of_the_range() {
+        let mut bot = bot();
+        let posts = [
+            ("Last one?", "2024-03-03", "23:59"),
+            ("Too early?", "2024-02-29", "23:59"),
+            ("First one?", "2024-03-01", "00:00"),
+            ("Too late?", "2024-03-04", "00:00"),
+        ];
+        for (text, day, time) in posts {
+            let mut question = Question::new(text.to_string());
+            question.answered = true;
+            question.answered_at = Some(at(day, time));
+            bot.state.questions.push(question);
+        }
+        bot.state
+            .questions
+            .push(Question::new("Never posted?".to_string()));
+
+        let from = at("2024-03-01", "00:00").date_naive();
+        let to = at("2024-03-03", "00:00").date_naive();
+        let texts = bot
+            .history_in_range(from, to)
+            .iter()
+            .map(|q| q.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(texts, ["First one?", "Last one?"]);
+        assert_eq!(bot.history_in_range(to, from).len(), 0);
+    }
+
+    #[test]
+    fn a_cron_schedule_with_seconds_fires_on_its_days_only() {
+        let mut bot = bot();
+        bot.merge(entries("Sweet or savoury?"));
+        bot.schedule = Some(parse_schedule("30 15 9 * * Mon,Thu").unwrap());
+        assert_eq!(
+            weekdays("30 15 9 * * Mon,Thu"),
+            [Weekday::Mon, Weekday::Thu]
+        );
+
+        let tuesday = at("2024-01-02", "10:00");
+        let fire = "2024-01-04T09:15:30Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(bot.next_post_instant(tuesday), fire);
+
+        assert!(
+            !bot.is_due(at("2024-01-04", "09:15")),
+            "not before the second"
+        );
+        assert!(bot.is_due(at("2024-01-04", "09:16")));
+        assert!(
+            !bot.is_due(at("2024-01-04", "09:17")),
+            "only the first tick after it"
+        );
+        assert!(!bot.is_due(at("2024-01-03", "09:16")));
+    }
+
+    #[tokio::test]
+    async fn a_deleted_question_stays_deleted_while_a_source_lists_it() {
+        let mut bot = bot_with_sources(&[(
+            "https://example.com/a",
+            "Favourite season?\nBest film this year?",
+        )]);
+        bot.load().await.unwrap();
+        let id = bot.state.questions[0].id;
+
+        assert_eq!(bot.delete(&id.to_string()[..8]).unwrap(), id);
+        bot.save().await.unwrap();
+        bot.restore().await.unwrap();
+        bot.last_fetch_time = None;
+        bot.load().await.unwrap();
+
+        let texts = bot
+            .state
+            .questions
+            .iter()
+            .map(|q| q.text.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(texts, ["Best film this year?"]);
+        assert!(bot.delete(&id.to_string()).is_err());
+    }
+
+    #[test]
+    fn a_midnight_cron_schedule_posts_once_a_day() {
+        let mut bot = bot();
+        bot.merge(entries("Sweet or savoury?\nMountains or sea?"));
+        bot.schedule = Some(parse_schedule("0 0 * * *").unwrap());
+
+        let evening = at("2024-03-01", "23:59");
+        assert!(!bot.is_due(evening));
+        assert_eq!(bot.next_post_instant(evening), at("2024-03-02", "00:00"));
+
+        let first = bot.dry_run_tick(at("2024-03-02", "00:00"), "").unwrap();
+        assert!(first.question_posted.is_some());
+        let again = bot.dry_run_tick(at("2024-03-02", "00:00"), "").unwrap();
+        assert_eq!(again.question_posted, None, "posted once per fire time");
+        assert_eq!(
+            bot.next_post_instant(at("2024-03-02", "00:01")),
+            at("2024-03-03", "00:00")
+        );
+    }
+
+    #[test]
+    fn the_uniqueness_window_holds_back_repeats_until_it_passes() {
+        let mut bot = bot();
+        bot.selection_mode = SelectionMode::AllowRepeats;
+        bot.uniqueness_window_days = Some(7);
+        bot.merge(entries("Recent one?\nOld favourite?"));
+        let now = at("2024-03-10", "12:00");
+        bot.state.questions[0].answered = true;
+        bot.state.questions[0].answered_at = Some(now - Duration::days(3));
+        bot.state.questions[1].answered = true;
+        bot.state.questions[1].answered_at = Some(now - Duration::days(30));
+        let (recent, old) = (bot.state.questions[0].id, bot.state.questions[1].id);
+
+        for _ in 0..20 {
+            assert_eq!(bot.next_question_id(now, &HashSet::new()), Some(old));
+        }
+
+        let later = now + Duration::days(4);
+        let picks = (0..50)
+            .filter_map(|_| bot.next_question_id(later, &HashSet::new()))
+            .collect::<HashSet<Uuid>>();
+        assert_eq!(picks, HashSet::from([recent, old]));
+    }
+
+    #[test]
+    fn repeats_keep_an_answered_pool_going() {
+        let mut bot = bot();
+        bot.merge(entries("Sweet or savoury?\nMountains or sea?"));
+        let mut day = at("2024-03-01", "00:00");
+        for _ in 0..2 {
+            assert!(bot.dry_run_tick(day, "").unwrap().question_posted.is_some());
+            day += Duration::days(1);
+        }
+        assert_eq!(bot.dry_run_tick(day, "").unwrap().question_posted, None);
+        day += Duration::days(1);
+
+        bot.selection_mode = SelectionMode::AllowRepeats;
+        for _ in 0..3 {
+            assert!(bot.dry_run_tick(day, "").unwrap().question_posted.is_some());
+            day += Duration::days(1);
+        }
+        assert_eq!(bot.pending_count(), 0);
+        assert_eq!(bot.state.post_count, 5);
+    }
+
+    #[tokio::test]
+    async fn a_recap_of_more_questions_than_fields_is_split_into_pages() {
+        let discord = MockDiscord::answering(Outcome::Post);
+        let mut bot = bot();
+        bot.hook = hook(&discord);
+        bot.monthly_recap_day = Some(31);
+        let march = at("2024-03-01", "09:00");
+        for i in 0..30 {
+            let mut question = Question::new(format!("Question {}?", i));
+            question.answered = true;
+            question.answered_at = Some(march + Duration::days(i));
+            bot.state.questions.push(question);
+        }
+        let mut february = Question::new("Last month?".to_string());
+        february.answered_at = Some(march - Duration::days(1));
+        bot.state.questions.push(february);
+
+        bot.monthly_recap(at("2024-03-31", "12:00")).await.unwrap();
+        bot.monthly_recap(at("2024-03-31", "12:01")).await.unwrap();
+
+        let sent = discord.sent();
+        let pages = sent
+            .iter()
+            .map(|payload| {
+                let embed = &payload["embeds"][0];
+                (
+                    embed["title"].as_str().unwrap().to_string(),
+                    embed["fields"].as_array().unwrap().len(),
+                )
+            })
+            .collect::<Vec<(String, usize)>>();
+        assert_eq!(
+            pages,
+            [
+                (
+                    "Questions of March 2024 (1/2)".to_string(),
+                    MAX_EMBED_FIELDS
+                ),
+                ("Questions of March 2024 (2/2)".to_string(), 5),
+            ]
+        );
+        assert_eq!(sent[1]["embeds"][0]["fields"][4]["value"], "Question 29?");
+    }
+
+    #[tokio::test]
+    async fn forum_posts_are_named_after_the_question_and_tagged() {
+        let discord = MockDiscord::answering(Outcome::Post);
+        let mut hook = hook(&discord);
+        let template = EmbedTemplate::default();
+        let question = Question::new("Mountains or sea?".to_string());
+
+        hook.send_embed_from_template(&template, &question, None, None)
+            .await
+            .unwrap();
+        hook.forum = true;
+        hook.forum_tags = vec![123, 456];
+        hook.send_embed_from_template(&template, &question, None, None)
+            .await
+            .unwrap();
+        let long = Question::new("Why? ".repeat(30));
+        hook.send_embed_from_template(&template, &long, None, None)
+            .await
+            .unwrap();
+
+        let sent = discord.sent();
+        assert!(!sent[0].contains_key("thread_name"));
+        assert!(!sent[0].contains_key("applied_tags"));
+        assert_eq!(sent[1]["thread_name"], "Mountains or sea?");
+        assert_eq!(sent[1]["applied_tags"], json!(["123", "456"]));
+        let name = sent[2]["thread_name"].as_str().unwrap();
+        assert_eq!(name.chars().count(), 100);
+        assert!(name.ends_with('…'));
+    }
+
+    #[test]
+    fn a_prioritized_question_goes_next_then_the_order_resumes() {
+        for mode in [SelectionMode::Sequential, SelectionMode::ShuffledCycle] {
+            let mut bot = bot();
+            bot.selection_mode = mode;
+            bot.merge(entries(
+                "Sweet or savoury?\nMountains or sea?\nEarly bird or night owl?",
+            ));
+            let last = bot.state.questions[2].id;
+            bot.state.questions[2].answered = true;
+
+            assert_eq!(bot.prioritize(&last.to_string()[..8]).unwrap(), last);
+            let picks = pick_batch(&mut bot, 3);
+            assert_eq!(picks[0], last, "{:?}", mode);
+            assert_eq!(picks.len(), 3, "{:?}", mode);
+            assert!(bot.state.questions.iter().all(|q| !q.priority));
+        }
+
+        let mut bot = bot();
+        assert!(bot.prioritize("0").is_err(), "nothing to match");
+    }
+
+    #[test]
+    fn reordering_moves_a_question_in_the_sequence() {
+        let mut bot = bot();
+        bot.selection_mode = SelectionMode::Sequential;
+        bot.merge(entries(
+            "Sweet or savoury?\nMountains or sea?\nEarly bird or night owl?",
+        ));
+        let ids = bot
+            .state
+            .questions
+            .iter()
+            .map(|q| q.id)
+            .collect::<Vec<Uuid>>();
+
+        bot.reorder(&ids[2].to_string(), -1).unwrap();
+        bot.reorder(&ids[0].to_string(), 1).unwrap();
+        assert_eq!(pick_batch(&mut bot, 3), [ids[2], ids[1], ids[0]]);
+    }
+
+    #[test]
+    fn every_algorithm_scores_the_same_pair() {
+        let mut bot = bot();
+        bot.dedup.threshold = 2;
+        bot.dedup.similarity_threshold = 0.98;
+        let (a, b) = (
+            "What's your favourite colour?",
+            "What's your favourite coluor?",
+        );
+
+        let scored = [
+            DeduplicationAlgorithm::Exact,
+            DeduplicationAlgorithm::Levenshtein,
+            DeduplicationAlgorithm::DamerauLevenshtein,
+            DeduplicationAlgorithm::JaroWinkler,
+        ]
+        .map(|algorithm| {
+            bot.dedup.algorithm = algorithm;
+            let (distance, similarity, merged) = bot.compare(a, b);
+            (distance, (similarity * 1000.0).round() / 1000.0, merged)
+        });
+        assert_eq!(
+            scored,
+            [
+                (1, 0.0, false),
+                (2, 0.931, false),
+                (1, 0.966, true),
+                (1, 0.993, true),
+            ]
+        );
+
+        // Only case and spacing apart, which every algorithm merges
+        for algorithm in [
+            DeduplicationAlgorithm::Exact,
+            DeduplicationAlgorithm::Levenshtein,
+            DeduplicationAlgorithm::DamerauLevenshtein,
+            DeduplicationAlgorithm::JaroWinkler,
+        ] {
+            bot.dedup.algorithm = algorithm;
+            assert!(
+                bot.compare(a, "what's your  favourite COLOUR?").2,
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn an_export_loads_back_into_the_same_questions() {
+        let mut original = bot();
+        original.plain_options.section_categories = true;
+        original.import(
+            "## Food\nTea or coffee? | Tea | Coffee -- @Ana\nBest pizza? ||| Meilleure pizza ?\n## Travel\nDream trip? !pinned\nAlready asked?",
+        )
+        .unwrap();
+        original.state.questions[3].answered = true;
+
+        let exported = original.export_plain();
+        let mut loaded = bot();
+        loaded.plain_options.section_categories = true;
+        assert_eq!(loaded.import(&exported).unwrap(), (3, 0, 0));
+
+        let fields = |bot: &Bot| {
+            bot.questions_in_order()
+                .into_iter()
+                .filter(|q| !q.answered)
+                .map(|q| {
+                    (
+                        q.text.clone(),
+                        q.author.clone(),
+                        q.category.clone(),
+                        q.poll_options.clone(),
+                        q.variants.clone(),
+                        q.pinned,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fields(&loaded), fields(&original));
+        assert_eq!(loaded.export_plain(), exported);
+    }
+
+    #[tokio::test]
+    async fn loads_within_the_cache_ttl_skip_the_fetch() {
+        let fetcher = Arc::new(CountingFetcher::default());
+        let fetches = || fetcher.0.load(std::sync::atomic::Ordering::SeqCst);
+        let mut bot = bot();
+        bot.urls = vec!["https://example.com/a".to_string()];
+        bot.fetcher = fetcher.clone();
+        bot.cache_source_ttl = Duration::hours(1);
+
+        assert!(bot.load().await.unwrap().is_some());
+        assert_eq!(
+            bot.load().await.unwrap(),
+            None,
+            "nothing is listed from the cache"
+        );
+        assert_eq!(fetches(), 1);
+        assert_eq!(bot.state.questions.len(), 1);
+
+        bot.last_fetch_time = bot.last_fetch_time.map(|last| last - Duration::hours(2));
+        assert!(bot.load().await.unwrap().is_some());
+        assert_eq!(fetches(), 2);
+
+        bot.cache_source_ttl = Duration::zero();
+        bot.load().await.unwrap();
+        assert_eq!(fetches(), 3);
+    }
+
+    #[test]
+    fn an_emoji_counts_as_one_edit_however_many_chars_it_has() {
+        let question = Question::new("Family 👨‍👩‍👧 night?".to_string());
+        let swapped = "Family 🍕 night?";
+        assert_eq!("👨‍👩‍👧".chars().count(), 5);
+
+        for algorithm in [
+            DeduplicationAlgorithm::Levenshtein,
+            DeduplicationAlgorithm::DamerauLevenshtein,
+        ] {
+            assert_eq!(question.distance(swapped, algorithm), 1, "{:?}", algorithm);
+            let similarity = question.similarity_with(swapped, algorithm);
+            assert!(
+                (similarity - (1.0 - 1.0 / 15.0)).abs() < 1e-9,
+                "{}",
+                similarity
+            );
+        }
+
+        let flag = Question::new("Trip to 🇫🇷?".to_string());
+        assert_eq!(
+            flag.distance("Trip to 🇩🇪?", DeduplicationAlgorithm::Levenshtein),
+            1
+        );
+        assert_eq!(
+            flag.distance("Trip ot 🇫🇷?", DeduplicationAlgorithm::DamerauLevenshtein),
+            1
+        );
+    }
+
+    fn checked_bot(discord: &Arc<MockDiscord>) -> Bot {
+        let mut bot = bot_with_sources(&[("file://questions.txt", "Up?\n")]);
+        bot.hook = hook(discord);
+        bot.storage = Box::new(JsonFileStorage::new(
+            std::env::temp_dir().join(format!("qotd-{}.json", Uuid::new_v4())),
+        ));
+        bot
+    }
+
+    fn errors(checks: &[ConfigCheck]) -> Vec<(&str, Option<&str>)> {
+        checks
+            .iter()
+            .map(|check| (check.check, check.error.as_deref()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn every_config_check_passes_on_a_working_setup() {
+        let bot = checked_bot(&Arc::new(MockDiscord::default()));
+
+        let checks = bot.check_config().await;
+
+        assert_eq!(
+            errors(&checks),
+            [
+                ("webhook", None),
+                ("source", None),
+                ("questions file", None)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_deleted_webhook_fails_its_check() {
+        let discord = Arc::new(MockDiscord {
+            webhook_gone: true,
+            ..Default::default()
+        });
+        let mut bot = checked_bot(&Arc::new(MockDiscord::default()));
+        bot.notification_hook = Some(hook(&discord));
+
+        let checks = bot.check_config().await;
+
+        assert_eq!(checks[1].target, "notification webhook");
+        assert_eq!(
+            errors(&checks),
+            [
+                ("webhook", None),
+                ("webhook", Some("Unknown Webhook")),
+                ("source", None),
+                ("questions file", None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_source_fails_its_check() {
+        let mut bot = checked_bot(&Arc::new(MockDiscord::default()));
+        bot.urls.push("file://gone.txt".to_string());
+
+        let checks = bot.check_config().await;
+
+        assert_eq!(checks[2].target, "file://gone.txt");
+        assert_eq!(
+            errors(&checks),
+            [
+                ("webhook", None),
+                ("source", None),
+                ("source", Some("file://gone.txt is unreachable")),
+                ("questions file", None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_questions_file_in_a_missing_directory_fails_its_check() {
+        let mut bot = checked_bot(&Arc::new(MockDiscord::default()));
+        let path = std::env::temp_dir()
+            .join(format!("qotd-{}", Uuid::new_v4()))
+            .join("questions.json");
+        bot.storage = Box::new(JsonFileStorage::new(&path));
+
+        let checks = bot.check_config().await;
+
+        let storage = checks.last().unwrap();
+        assert_eq!(storage.check, "questions file");
+        assert_eq!(storage.target, bot.storage.location());
+        assert!(storage.error.is_some());
+        assert!(checks[..2].iter().all(|check| check.error.is_none()));
+    }
 }
@@ -0,0 +1,559 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{json, Value};
+use std::{path::PathBuf, str::FromStr};
+use structopt::StructOpt;
+
+use crate::bot::{Bot, ConfigCheck, Question};
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Show the questions that were posted today
+    Today,
+    /// Show how many questions are left and the current posting streak
+    Stats,
+    /// Show when the next question will be posted
+    Next {
+        /// Also show which question, when that's already decided
+        #[structopt(long = "preview")]
+        preview: bool,
+    },
+    /// Show the audit log, oldest entries first
+    Audit,
+    /// Check the question sources for problems without running the bot
+    Validate,
+    /// Post a sample question to check how the embed looks, without storing anything
+    TestEmbed,
+    /// Add a question that isn't in any of the sources
+    Submit {
+        text: String,
+        #[structopt(long = "category")]
+        category: Option<String>,
+    },
+    /// Add a question to be posted on a given day, ahead of the others
+    Schedule {
+        date: NaiveDate,
+        text: String,
+        #[structopt(long = "category")]
+        category: Option<String>,
+    },
+    /// Stop posting questions until resumed, while the sources are still loaded
+    Pause,
+    /// Start posting questions again after a pause
+    Resume,
+    /// Show every stored question in the order --sequential posts them
+    List,
+    /// Change where a question comes in the --sequential order
+    Reorder {
+        /// The question's id, or enough of its start to be unique
+        id: String,
+        #[structopt(long = "sort_order", allow_hyphen_values = true)]
+        sort_order: i64,
+    },
+    /// Post a question next, ahead of the usual pick
+    Prioritize {
+        /// The question's id, or enough of its start to be unique
+        id: String,
+    },
+    /// Remove a question for good, even if a source still lists it
+    Delete {
+        /// The question's id, or enough of its start to be unique
+        id: String,
+    },
+    /// Print the unanswered questions as a plain source, ready to paste into a pastebin
+    Export {
+        /// Write to this file instead of stdout
+        #[structopt(long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Add every line of a block of text as a question, merging duplicates like the sources are
+    Import {
+        /// Questions, one per line
+        #[structopt(required_unless = "file")]
+        text: Option<String>,
+        /// Read the questions from a text file instead
+        #[structopt(long = "file", conflicts_with = "text")]
+        file: Option<PathBuf>,
+    },
+    /// Run one tick against a local source file, without posting or saving anything
+    DryRun {
+        source: PathBuf,
+        /// Time to run the tick at, in RFC 3339, instead of now
+        #[structopt(long = "at")]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Show the questions posted between two dates, both inclusive
+    History {
+        #[structopt(long = "from")]
+        from: NaiveDate,
+        #[structopt(long = "to")]
+        to: NaiveDate,
+        /// text, json or csv
+        #[structopt(long = "format", default_value = "text")]
+        format: HistoryFormat,
+        /// Write to this file instead of stdout
+        #[structopt(long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Show the stored questions whose text matches a regular expression
+    Search {
+        pattern: String,
+        #[structopt(long = "case_insensitive")]
+        case_insensitive: bool,
+    },
+    /// Check the webhooks, the sources and the questions file without starting the bot
+    CheckConfig {
+        /// text or json
+        #[structopt(long = "format", default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Show how two lines compare under the dedup options, for tuning them
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    Distance { a: String, b: String },
+}
+
+#[derive(Debug)]
+pub enum HistoryFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for HistoryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(HistoryFormat::Text),
+            "json" => Ok(HistoryFormat::Json),
+            "csv" => Ok(HistoryFormat::Csv),
+            _ => Err(format!("Unknown history format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("Unknown report format: {}", s)),
+        }
+    }
+}
+
+impl ReportFormat {
+    fn render_checks(&self, checks: &[ConfigCheck]) -> Result<String, Err> {
+        let mut out = String::new();
+        match self {
+            ReportFormat::Text => {
+                for check in checks {
+                    out += &match &check.error {
+                        Some(e) => format!("FAIL {} {}: {}\n", check.check, check.target, e),
+                        None => format!("ok   {} {}\n", check.check, check.target),
+                    };
+                }
+            }
+            ReportFormat::Json => {
+                let ok = checks.iter().all(|check| check.error.is_none());
+                let report = json!({ "ok": ok, "checks": checks });
+                out = serde_json::to_string_pretty(&report)? + "\n";
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Fails with how many checks did, so the process exits with an error code
+fn any_failed(checks: &[ConfigCheck]) -> Result<(), Err> {
+    let failed = checks.iter().filter(|check| check.error.is_some()).count();
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of {} checks failed", failed, checks.len()).into()),
+    }
+}
+
+impl HistoryFormat {
+    fn render(&self, questions: &[&Question]) -> Result<String, Err> {
+        let mut out = String::new();
+        match self {
+            HistoryFormat::Text => {
+                for question in questions {
+                    out += &format!(
+                        "{} {} [{}] {}\n",
+                        posted_at(question),
+                        question.id(),
+                        question.category().unwrap_or("-"),
+                        question.text()
+                    );
+                }
+            }
+            HistoryFormat::Json => {
+                let rows = questions
+                    .iter()
+                    .map(|question| {
+                        json!({
+                            "id": question.id(),
+                            "text": question.text(),
+                            "category": question.category(),
+                            "answered_at": question.answered_at(),
+                        })
+                    })
+                    .collect::<Vec<Value>>();
+                out = serde_json::to_string_pretty(&rows)? + "\n";
+            }
+            HistoryFormat::Csv => {
+                out += "answered_at,id,text,category\n";
+                for question in questions {
+                    out += &format!(
+                        "{},{},{},{}\n",
+                        posted_at(question),
+                        question.id(),
+                        csv_field(question.text()),
+                        csv_field(question.category().unwrap_or_default())
+                    );
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+fn posted_at(question: &Question) -> String {
+    question
+        .answered_at()
+        .map(|answered_at| answered_at.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// Quotes a CSV field when it contains anything that would break the row apart
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+
+    value.to_string()
+}
+
+impl Command {
+    pub async fn run(self, bot: &mut Bot) -> Result<(), Err> {
+        match self {
+            Command::Today => {
+                bot.restore().await?;
+
+                let questions = bot.questions_posted_on(Utc::now().date_naive());
+                for question in &questions {
+                    println!("{}", question.text());
+                }
+                if questions.is_empty() {
+                    println!("Nothing posted yet today");
+                }
+            }
+            Command::Stats => {
+                bot.restore().await?;
+
+                println!("Questions: {}", bot.state.questions.len());
+                println!("Unanswered: {}", bot.pending_count());
+                println!("Streak: {} days", bot.streak(Utc::now().date_naive()));
+            }
+            Command::Next { preview } => {
+                bot.restore().await?;
+
+                println!("Next post at {}", bot.next_post_instant(Utc::now()));
+                if preview {
+                    match bot.peek_next_question(Utc::now()) {
+                        Some(question) => println!("{} {}", question.id(), question.text()),
+                        None => println!("The next question isn't decided yet"),
+                    }
+                }
+            }
+            Command::Audit => {
+                bot.restore().await?;
+
+                for entry in &bot.state.audit_log {
+                    println!("{}", entry);
+                }
+            }
+            Command::Validate => {
+                let mut problems = 0;
+                for report in bot.validate_sources().await? {
+                    println!("{}: {} questions", report.url, report.questions);
+                    for problem in &report.problems {
+                        println!("{}: {}", report.url, problem);
+                    }
+                    problems += report.problems.len();
+                }
+
+                if problems > 0 {
+                    return Err(format!("Found {} problems", problems).into());
+                }
+            }
+            Command::TestEmbed => {
+                let embed = bot.send_test_embed().await?;
+                println!("{}", serde_json::to_string_pretty(&embed)?);
+            }
+            Command::Submit { text, category } => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                if let Some(id) = bot.submit(text, category) {
+                    println!("{}", id);
+                }
+                bot.save().await?;
+            }
+            Command::Schedule {
+                date,
+                text,
+                category,
+            } => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                if let Some(id) = bot.schedule_question(date, text, category) {
+                    println!("{} will be posted on {}", id, date);
+                }
+                bot.save().await?;
+            }
+            Command::Pause => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                match bot.set_paused(true) {
+                    true => println!("Posting paused"),
+                    false => println!("Posting was already paused"),
+                }
+                bot.save().await?;
+            }
+            Command::Resume => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                match bot.set_paused(false) {
+                    true => println!("Posting resumed"),
+                    false => println!("Posting wasn't paused"),
+                }
+                bot.save().await?;
+            }
+            Command::List => {
+                bot.restore().await?;
+
+                for question in bot.questions_in_order() {
+                    let status = match question.answered() {
+                        true => "answered",
+                        false => "unanswered",
+                    };
+                    println!(
+                        "{} {} [{}] {}",
+                        question.sort_order(),
+                        question.id(),
+                        status,
+                        question.text()
+                    );
+                }
+            }
+            Command::Reorder { id, sort_order } => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                let id = bot.reorder(&id, sort_order)?;
+                bot.save().await?;
+                println!("Moved {} to {}", id, sort_order);
+            }
+            Command::Prioritize { id } => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                let id = bot.prioritize(&id)?;
+                bot.save().await?;
+                println!("{} will be posted next", id);
+            }
+            Command::Delete { id } => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                let id = bot.delete(&id)?;
+                bot.save().await?;
+                println!("Deleted {}", id);
+            }
+            Command::Import { text, file } => {
+                let _lock = bot.lock().await?;
+                bot.restore().await?;
+
+                let raw = match file {
+                    Some(file) => tokio::fs::read_to_string(file).await?,
+                    None => text.unwrap_or_default(),
+                };
+                let (added, updated, skipped) = bot.import(&raw)?;
+                bot.save().await?;
+
+                println!("Added: {}", added);
+                println!("Updated: {}", updated);
+                println!("Skipped: {}", skipped);
+            }
+            Command::DryRun { source, at } => {
+                bot.restore().await?;
+
+                let source = tokio::fs::read_to_string(source).await?;
+                let result = bot.dry_run_tick(at.unwrap_or_else(Utc::now), &source)?;
+                if let Some(id) = result.intro_announced {
+                    println!("Announced: {}", id);
+                }
+                match result.question_posted {
+                    Some(id) => println!("Posted: {}", id),
+                    None => println!("Posted: nothing"),
+                }
+                println!("Added: {}", result.questions_added);
+                println!("Updated: {}", result.questions_updated);
+                println!("Removed: {}", result.questions_removed);
+            }
+            Command::Export { output } => {
+                bot.restore().await?;
+
+                let exported = bot.export_plain();
+                match output {
+                    Some(path) => tokio::fs::write(path, exported).await?,
+                    None => print!("{}", exported),
+                }
+            }
+            Command::History {
+                from,
+                to,
+                format,
+                output,
+            } => {
+                bot.restore().await?;
+
+                let questions = bot.history_in_range(from, to);
+                let formatted = format.render(&questions)?;
+                match output {
+                    Some(path) => tokio::fs::write(path, formatted).await?,
+                    None => print!("{}", formatted),
+                }
+            }
+            Command::CheckConfig { format } => {
+                let checks = bot.check_config().await;
+                print!("{}", format.render_checks(&checks)?);
+                any_failed(&checks)?;
+            }
+            Command::Distance { a, b } => {
+                let (distance, similarity, merged) = bot.compare(&a, &b);
+                println!("Edit distance: {}", distance);
+                println!("Similarity: {:.2}", similarity);
+                match merged {
+                    true => println!("These would be merged"),
+                    false => println!("These would be kept apart"),
+                }
+            }
+            Command::Search {
+                pattern,
+                case_insensitive,
+            } => {
+                bot.restore().await?;
+
+                let pattern = match case_insensitive {
+                    true => format!("(?i){}", pattern),
+                    false => pattern,
+                };
+                let questions = bot.questions_matching_pattern(&pattern)?;
+                for question in &questions {
+                    let status = match question.answered() {
+                        true => "answered",
+                        false => "unanswered",
+                    };
+                    println!("{} [{}] {}", question.id(), status, question.text());
+                }
+
+                if questions.is_empty() {
+                    return Err("No questions matched".into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checks() -> Vec<ConfigCheck> {
+        vec![
+            ConfigCheck {
+                check: "webhook",
+                target: "question webhook".to_string(),
+                error: Some("Unknown Webhook".to_string()),
+            },
+            ConfigCheck {
+                check: "source",
+                target: "https://example.com/questions.txt".to_string(),
+                error: None,
+            },
+            ConfigCheck {
+                check: "questions file",
+                target: "questions.json".to_string(),
+                error: Some("Permission denied".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn the_json_report_lists_every_check_and_its_error() {
+        let report = ReportFormat::Json.render_checks(&checks()).unwrap();
+        let report: Value = serde_json::from_str(&report).unwrap();
+
+        assert_eq!(report["ok"], false);
+        assert_eq!(
+            report["checks"],
+            json!([
+                {"check": "webhook", "target": "question webhook", "error": "Unknown Webhook"},
+                {"check": "source", "target": "https://example.com/questions.txt", "error": null},
+                {"check": "questions file", "target": "questions.json", "error": "Permission denied"},
+            ])
+        );
+    }
+
+    #[test]
+    fn the_json_report_is_ok_when_every_check_passes() {
+        let mut checks = checks();
+        for check in &mut checks {
+            check.error = None;
+        }
+
+        let report = ReportFormat::Json.render_checks(&checks).unwrap();
+        let report: Value = serde_json::from_str(&report).unwrap();
+
+        assert_eq!(report["ok"], true);
+        assert!(any_failed(&checks).is_ok());
+    }
+
+    #[test]
+    fn the_text_report_marks_each_check() {
+        let report = ReportFormat::Text.render_checks(&checks()).unwrap();
+
+        assert_eq!(
+            report,
+            "FAIL webhook question webhook: Unknown Webhook\n\
+             ok   source https://example.com/questions.txt\n\
+             FAIL questions file questions.json: Permission denied\n"
+        );
+    }
+
+    #[test]
+    fn failed_checks_make_an_error() {
+        let e = any_failed(&checks()).unwrap_err();
+
+        assert_eq!(e.to_string(), "2 of 3 checks failed");
+    }
+}
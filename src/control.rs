@@ -0,0 +1,147 @@
+use hyper::{
+    header::AUTHORIZATION,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::sync::{mpsc, oneshot};
+use tracing::info;
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Asks a bot to post right away, answered once the post went out or failed
+pub type PostRequest = oneshot::Sender<Result<(), String>>;
+
+/// HTTP endpoint for posting on demand, `POST /post` with the token as a bearer token.
+/// With several question sets, `?set=name` picks the one to post from.
+pub struct ControlServer {
+    pub host: IpAddr,
+    pub port: u16,
+    pub token: String,
+    /// Where the post requests for each question set go
+    pub sets: HashMap<String, mpsc::Sender<PostRequest>>,
+}
+
+impl ControlServer {
+    pub async fn serve(self) -> Result<(), Err> {
+        let addr = SocketAddr::from((self.host, self.port));
+        let server = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(req).await) }
+                }))
+            }
+        });
+
+        info!("Control endpoint listening on {}", addr);
+        Server::try_bind(&addr)?.serve(make_service).await?;
+
+        Ok(())
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        if (req.method(), req.uri().path()) != (&Method::POST, "/post") {
+            return reply(StatusCode::NOT_FOUND, "Not found");
+        }
+
+        let expected = format!("Bearer {}", self.token);
+        let authorized = req
+            .headers()
+            .get(AUTHORIZATION)
+            .is_some_and(|value| same_bytes(value.as_bytes(), expected.as_bytes()));
+        if !authorized {
+            return reply(StatusCode::UNAUTHORIZED, "Missing or wrong token");
+        }
+
+        let set = req.uri().query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "set")
+                .map(|(_, value)| value.into_owned())
+        });
+        let posts = match (set, self.sets.len()) {
+            (Some(name), _) => self.sets.get(&name),
+            (None, 1) => self.sets.values().next(),
+            (None, _) => return reply(StatusCode::BAD_REQUEST, "Pick a question set with ?set="),
+        };
+        let posts = match posts {
+            Some(posts) => posts,
+            None => return reply(StatusCode::NOT_FOUND, "No such question set"),
+        };
+
+        let (done, result) = oneshot::channel();
+        if posts.send(done).await.is_err() {
+            return reply(StatusCode::SERVICE_UNAVAILABLE, "Bot is not running");
+        }
+
+        match result.await {
+            Ok(Ok(())) => reply(StatusCode::OK, "Posted"),
+            Ok(Err(e)) => reply(StatusCode::INTERNAL_SERVER_ERROR, &e),
+            Err(_) => reply(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Bot stopped before posting",
+            ),
+        }
+    }
+}
+
+/// Compares in a time that only depends on the lengths, so the token can't be guessed
+/// one byte at a time
+fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn reply(status: StatusCode, text: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(text.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> ControlServer {
+        let (posts, _) = mpsc::channel(1);
+        ControlServer {
+            host: [127, 0, 0, 1].into(),
+            port: 0,
+            token: "secret".to_string(),
+            sets: HashMap::from([("main".to_string(), posts)]),
+        }
+    }
+
+    fn post(uri: &str, authorization: &str) -> Request<Body> {
+        Request::post(uri)
+            .header(AUTHORIZATION, authorization)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_a_wrong_token() {
+        for token in ["Bearer secreT", "Bearer secret2", "Bearer ", "secret"] {
+            let response = server().handle(post("/post", token)).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", token);
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_the_token_and_looks_up_the_set() {
+        let response = server()
+            .handle(post("/post?set=other", "Bearer secret"))
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // The bot on the other end of the channel is gone
+        let response = server().handle(post("/post", "Bearer secret")).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
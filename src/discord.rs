@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use serenity::{
+    http::{Http, StatusCode},
+    model::channel::Message,
+};
+use std::{error::Error, fmt};
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Where a message was posted, all that's kept of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PostedMessage {
+    pub channel_id: u64,
+    pub id: u64,
+}
+
+impl From<Message> for PostedMessage {
+    fn from(message: Message) -> Self {
+        Self {
+            channel_id: message.channel_id.0,
+            id: message.id.0,
+        }
+    }
+}
+
+/// Discord turned a message away without posting it, because it was overloaded or rate
+/// limited, so sending it again can't post it twice
+#[derive(Debug)]
+pub struct Unavailable(pub String);
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Discord didn't take the message: {}", self.0)
+    }
+}
+
+impl Error for Unavailable {}
+
+/// The calls to Discord a webhook makes
+#[async_trait]
+pub trait DiscordApi: fmt::Debug + Send + Sync {
+    /// Posts through a webhook, waiting for the message it became
+    async fn execute_webhook(
+        &self,
+        id: u64,
+        token: &str,
+        payload: &Map<String, Value>,
+    ) -> Result<Option<PostedMessage>, Err>;
+    async fn edit_webhook_message(
+        &self,
+        id: u64,
+        token: &str,
+        message_id: u64,
+        payload: &Map<String, Value>,
+    ) -> Result<PostedMessage, Err>;
+    /// Fails unless the id and token belong to a webhook
+    async fn get_webhook(&self, id: u64, token: &str) -> Result<(), Err>;
+    /// Starts a thread on a message, returning the id of the thread
+    async fn create_public_thread(
+        &self,
+        bot_token: &str,
+        message: PostedMessage,
+        payload: &Map<String, Value>,
+    ) -> Result<u64, Err>;
+    /// Adds up the reactions on a message
+    async fn reaction_count(&self, bot_token: &str, message: PostedMessage) -> Result<u32, Err>;
+}
+
+/// Calls Discord's HTTP API through serenity
+#[derive(Debug)]
+pub struct SerenityApi;
+
+#[async_trait]
+impl DiscordApi for SerenityApi {
+    async fn execute_webhook(
+        &self,
+        id: u64,
+        token: &str,
+        payload: &Map<String, Value>,
+    ) -> Result<Option<PostedMessage>, Err> {
+        let message = Http::new_with_token(token)
+            .execute_webhook(id, token, true, payload)
+            .await
+            .map_err(|e| -> Err {
+                match &e {
+                    serenity::Error::Http(http)
+                        if http.status_code().is_some_and(|status| {
+                            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+                        }) =>
+                    {
+                        Box::new(Unavailable(e.to_string()))
+                    }
+                    _ => e.into(),
+                }
+            })?;
+
+        Ok(message.map(PostedMessage::from))
+    }
+
+    async fn edit_webhook_message(
+        &self,
+        id: u64,
+        token: &str,
+        message_id: u64,
+        payload: &Map<String, Value>,
+    ) -> Result<PostedMessage, Err> {
+        let message = Http::new_with_token(token)
+            .edit_webhook_message(id, token, message_id, payload)
+            .await?;
+
+        Ok(message.into())
+    }
+
+    async fn get_webhook(&self, id: u64, token: &str) -> Result<(), Err> {
+        Http::new_with_token(token)
+            .get_webhook_with_token(id, token)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_public_thread(
+        &self,
+        bot_token: &str,
+        message: PostedMessage,
+        payload: &Map<String, Value>,
+    ) -> Result<u64, Err> {
+        let thread = Http::new_with_token(bot_token)
+            .create_public_thread(message.channel_id, message.id, payload)
+            .await?;
+
+        Ok(thread.id.0)
+    }
+
+    async fn reaction_count(&self, bot_token: &str, message: PostedMessage) -> Result<u32, Err> {
+        let message = Http::new_with_token(bot_token)
+            .get_message(message.channel_id, message.id)
+            .await?;
+
+        Ok(message
+            .reactions
+            .iter()
+            .map(|reaction| reaction.count as u32)
+            .sum())
+    }
+}
@@ -0,0 +1,557 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, NaiveDate,
+};
+use chrono_tz::Tz;
+use serde_json::Value;
+use serenity::model::channel::Embed;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+use tracing::warn;
+use url::Url;
+use uuid::Uuid;
+
+use crate::bot::Question;
+
+/// Discord rejects embeds whose text adds up to more than this
+const MAX_EMBED_LENGTH: usize = 6000;
+/// Discord rejects embeds with more fields than this
+pub const MAX_EMBED_FIELDS: usize = 25;
+/// Questions in a recap are shortened to this, so a full page stays within the embed limit
+const MAX_RECAP_QUESTION_LENGTH: usize = 200;
+
+/// Layout of the embed a question is posted with
+#[derive(Debug, Clone)]
+pub struct EmbedTemplate {
+    pub title: String,
+    /// `{question}` is replaced with the question text
+    pub description_template: String,
+    /// Longer questions are cut at a word boundary to fit
+    pub max_description_length: usize,
+    pub color: EmbedColor,
+    /// Colors of specific categories, taking precedence over `color`
+    pub category_colors: Vec<CategoryColor>,
+    /// Used by `EmbedColor::Automatic` for questions without a category
+    pub fallback_color: u32,
+    /// `{time}` is replaced with the time the question is posted at.
+    /// Questions with a known submitter credit them instead.
+    pub footer_template: String,
+    /// chrono format string used for `{time}`
+    pub time_format: String,
+    pub show_question_number: bool,
+    /// Number the title with the count of questions posted so far
+    pub show_post_count: bool,
+    pub show_category: bool,
+    /// Small image in the corner, a URL template like `url`
+    pub thumbnail_url: Option<String>,
+    /// Link on the title, with placeholders filled in by `resolve_template`
+    pub url: Option<String>,
+    /// Shown above the title, usually for branding
+    pub author_name: Option<String>,
+    pub author_icon_url: Option<String>,
+    /// Which translation of the question to post, 0 being the original text
+    pub variant: usize,
+    /// Color of the embed posted when the bot starts, kept apart from questions
+    pub announcement_color: u32,
+    /// End the description with a blank line, made of a zero-width space so Discord keeps it
+    pub padding: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum EmbedColor {
+    Fixed(u32),
+    /// Derived from the question's category, so every category keeps its own color
+    Automatic,
+}
+
+/// The color of a category's embeds, written as `category=#rrggbb`
+#[derive(Debug, Clone)]
+pub struct CategoryColor {
+    category: String,
+    color: u32,
+}
+
+/// Everything about a post that isn't part of the question itself
+pub struct RenderContext {
+    pub now: DateTime<Tz>,
+    /// 1-based position of the question and the total number of questions
+    pub number: Option<(usize, usize)>,
+    /// How many questions have been posted, this one included
+    pub post_count: Option<u64>,
+}
+
+impl EmbedTemplate {
+    pub fn render(&self, question: &Question, ctx: &RenderContext) -> Value {
+        let text = question.localized_text(self.variant);
+        let length = text.chars().count();
+        let description = if length > self.max_description_length {
+            warn!(
+                "Truncating question {} from {} characters",
+                question.id(),
+                length
+            );
+            self.description_template.replace(
+                "{question}",
+                &truncate_at_word(text, self.max_description_length),
+            )
+        } else {
+            self.description_template.replace("{question}", text)
+        };
+
+        let mut footer = match question.author() {
+            Some(name) => format!("Submitted by {}", name),
+            None => self
+                .footer_template
+                .replace("{time}", &ctx.now.format(&self.time_format).to_string()),
+        };
+        if let (true, Some((number, total))) = (self.show_question_number, ctx.number) {
+            footer = format!("Question #{} of {} • {}", number, total, footer);
+        }
+
+        let mut fields = vec![];
+        if let (true, Some(category)) = (self.show_category, question.category()) {
+            fields.push(("Category", category, true));
+        }
+        fields.extend(
+            question
+                .custom_fields()
+                .iter()
+                .map(|(name, value, inline)| (name.as_str(), value.as_str(), *inline)),
+        );
+        if fields.len() > MAX_EMBED_FIELDS {
+            warn!(
+                "Dropping {} fields of question {}, Discord accepts at most {}",
+                fields.len() - MAX_EMBED_FIELDS,
+                question.id(),
+                MAX_EMBED_FIELDS
+            );
+            fields.truncate(MAX_EMBED_FIELDS);
+        }
+
+        let title = match ctx.post_count {
+            Some(count) => format!("{} #{}", self.title, count),
+            None => self.title.clone(),
+        };
+
+        Embed::fake(|e| {
+            if let Some(name) = self.author_name.as_deref().filter(|name| !name.is_empty()) {
+                e.author(|a| {
+                    a.name(name);
+                    if let Some(url) = &self.author_icon_url {
+                        a.icon_url(url);
+                    }
+                    a
+                });
+            }
+            e.title(title);
+            if let Some(url) = &self.url {
+                e.url(resolve_template(url, question));
+            }
+            match self.padding {
+                true => e.description(description + "\n\u{200B}"),
+                false => e.description(description),
+            };
+            e.colour(self.color_for(question.category()));
+            e.fields(fields);
+            if let Some(url) = &self.thumbnail_url {
+                e.thumbnail(resolve_template(url, question));
+            }
+            if let Some(url) = question.embed_image_url() {
+                e.image(url);
+            }
+            e.footer(|f| {
+                f.text(footer);
+                f
+            });
+            e
+        })
+    }
+
+    /// Teaser posted ahead of the question
+    pub fn render_intro(&self, category: Option<&str>) -> Value {
+        let description = match category {
+            Some(category) => format!("Today's QOTD category: {} — Get ready!", category),
+            None => "Today's QOTD is coming up — Get ready!".to_string(),
+        };
+
+        Embed::fake(|e| {
+            e.title(&self.title);
+            e.description(description);
+            e.colour(self.color_for(category));
+            e
+        })
+    }
+
+    /// Status message about the bot itself
+    pub fn render_announcement(&self, text: &str) -> Value {
+        Embed::fake(|e| {
+            e.description(text);
+            e.colour(self.announcement_color);
+            e
+        })
+    }
+
+    /// One page of the questions posted in a month, with one field per question
+    pub fn render_monthly_recap(
+        &self,
+        month: NaiveDate,
+        questions: &[&Question],
+        page: usize,
+        pages: usize,
+    ) -> Value {
+        let mut title = format!("Questions of {}", month.format("%B %Y"));
+        if pages > 1 {
+            title = format!("{} ({}/{})", title, page, pages);
+        }
+
+        Embed::fake(|e| {
+            e.title(title);
+            e.colour(self.announcement_color);
+            for question in questions {
+                let posted = question
+                    .answered_at()
+                    .map(|answered_at| answered_at.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default();
+                let mut text = question.localized_text(self.variant).to_string();
+                if text.chars().count() > MAX_RECAP_QUESTION_LENGTH {
+                    text = truncate_at_word(&text, MAX_RECAP_QUESTION_LENGTH);
+                }
+                e.field(posted, text, false);
+            }
+            e
+        })
+    }
+
+    /// The most reacted questions of the week, best first
+    pub fn render_leaderboard(&self, questions: &[&Question]) -> Value {
+        Embed::fake(|e| {
+            e.title("Most popular questions of the week");
+            e.colour(self.announcement_color);
+            for (place, question) in questions.iter().enumerate() {
+                let mut text = question.localized_text(self.variant).to_string();
+                if text.chars().count() > MAX_RECAP_QUESTION_LENGTH {
+                    text = truncate_at_word(&text, MAX_RECAP_QUESTION_LENGTH);
+                }
+                let reactions = question.reaction_count().unwrap_or_default();
+                e.field(
+                    format!("#{} with {} reactions", place + 1, reactions),
+                    text,
+                    false,
+                );
+            }
+            e
+        })
+    }
+
+    fn color_for(&self, category: Option<&str>) -> u32 {
+        let configured = self
+            .category_colors
+            .iter()
+            .find(|c| Some(c.category.as_str()) == category);
+        if let Some(configured) = configured {
+            return configured.color;
+        }
+
+        match (self.color, category) {
+            (EmbedColor::Fixed(color), _) => color,
+            (EmbedColor::Automatic, Some(category)) => category_color(category),
+            (EmbedColor::Automatic, None) => self.fallback_color,
+        }
+    }
+}
+
+impl Default for EmbedTemplate {
+    fn default() -> Self {
+        Self {
+            title: ":question: :grey_question: Question of the day :grey_question: :question:"
+                .to_string(),
+            description_template: "{question}".to_string(),
+            max_description_length: 1024,
+            color: EmbedColor::Fixed(0xff0000),
+            category_colors: vec![],
+            fallback_color: 0xff0000,
+            footer_template: "Asked by Hawk's bot at {time}".to_string(),
+            time_format: "%Y-%m-%d %H:%M:%S %Z".to_string(),
+            show_question_number: false,
+            show_post_count: false,
+            show_category: false,
+            thumbnail_url: None,
+            url: None,
+            author_name: None,
+            author_icon_url: None,
+            variant: 0,
+            announcement_color: 0x00ff00,
+            padding: true,
+        }
+    }
+}
+
+/// Shortens the description of an embed whose text adds up to more than Discord accepts
+pub fn fit_embed(embed: &mut Value) -> Result<(), String> {
+    let length = embed_length(embed);
+    if length <= MAX_EMBED_LENGTH {
+        return Ok(());
+    }
+
+    let description = embed["description"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let description_length = description.chars().count();
+    let excess = length - MAX_EMBED_LENGTH;
+    if excess >= description_length {
+        return Err(format!(
+            "Embed is {} characters without its description, Discord accepts at most {}",
+            length - description_length,
+            MAX_EMBED_LENGTH
+        ));
+    }
+
+    warn!("Shortening an embed of {} characters to fit", length);
+    embed["description"] = Value::from(truncate_at_word(&description, description_length - excess));
+
+    Ok(())
+}
+
+/// Characters Discord counts towards the limit of an embed
+fn embed_length(embed: &Value) -> usize {
+    let count = |value: &Value| value.as_str().map_or(0, |s| s.chars().count());
+    let fields = embed["fields"].as_array().map_or(0, |fields| {
+        fields
+            .iter()
+            .map(|field| count(&field["name"]) + count(&field["value"]))
+            .sum()
+    });
+
+    count(&embed["title"])
+        + count(&embed["description"])
+        + count(&embed["footer"]["text"])
+        + count(&embed["author"]["name"])
+        + fields
+}
+
+/// Cuts `text` to at most `max` characters at the last word boundary, ending it with an ellipsis
+fn truncate_at_word(text: &str, max: usize) -> String {
+    // Leave room for the ellipsis
+    let prefix = text.chars().take(max.saturating_sub(1)).collect::<String>();
+    let cut = match prefix.rfind(char::is_whitespace) {
+        Some(index) => prefix[..index].trim_end(),
+        None => prefix.as_str(),
+    };
+
+    format!("{}…", cut)
+}
+
+impl FromStr for EmbedColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" | "automatic" => Ok(EmbedColor::Automatic),
+            _ => parse_color(s).map(EmbedColor::Fixed),
+        }
+    }
+}
+
+impl FromStr for CategoryColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (category, color) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected a color like trivia=#00ff00, got {}", s))?;
+
+        Ok(Self {
+            category: category.trim().to_string(),
+            color: parse_color(color.trim())?,
+        })
+    }
+}
+
+/// Picks a color from the hash of a category, light and saturated enough to read on a dark background
+fn category_color(category: &str) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    category.hash(&mut hasher);
+    let hue = (hasher.finish() % 360) as f64;
+
+    hsl_to_rgb(hue, 0.65, 0.55)
+}
+
+/// Converts a hue in degrees and a saturation and lightness between 0 and 1 to `0xRRGGBB`
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> u32 {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+    let (r, g, b) = match hue as u32 / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let channel = |value: f64| ((value + m) * 255.0).round() as u32;
+    (channel(r) << 16) | (channel(g) << 8) | channel(b)
+}
+
+/// Parses a color written as `ff0000`, `#ff0000` or `0xff0000`
+pub fn parse_color(s: &str) -> Result<u32, String> {
+    let hex = s
+        .trim_start_matches('#')
+        .trim_start_matches("0x")
+        .trim_start_matches("0X");
+
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|color| *color <= 0xffffff)
+        .ok_or_else(|| format!("Invalid color: {}", s))
+}
+
+/// Fills in `{question_id}` and the URL encoded `{category}` of a URL template
+pub fn resolve_template(template: &str, question: &Question) -> String {
+    let category =
+        url::form_urlencoded::byte_serialize(question.category().unwrap_or_default().as_bytes())
+            .collect::<String>();
+
+    template
+        .replace("{question_id}", &question.id().to_string())
+        .replace("{category}", &category)
+}
+
+/// Checks that a URL template is an HTTPS URL once its placeholders are filled in
+pub fn parse_url_template(s: &str) -> Result<String, String> {
+    let sample = s
+        .replace("{question_id}", &Uuid::nil().to_string())
+        .replace("{category}", "category");
+    let url = Url::parse(&sample).map_err(|e| format!("Invalid URL {}: {}", s, e))?;
+    if url.scheme() != "https" {
+        return Err(format!("URL must use https: {}", s));
+    }
+
+    Ok(s.to_string())
+}
+
+/// Checks that a chrono format string is valid, since formatting with an invalid one panics
+pub fn parse_time_format(s: &str) -> Result<String, String> {
+    if StrftimeItems::new(s).any(|item| item == Item::Error) {
+        return Err(format!("Invalid time format: {}", s));
+    }
+
+    Ok(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{Entry, PlainOptions, SourceFormat};
+
+    #[test]
+    fn footer_credits_the_submitter() {
+        let template = EmbedTemplate {
+            footer_template: "Asked at {time}".to_string(),
+            time_format: "%H:%M".to_string(),
+            ..EmbedTemplate::default()
+        };
+        let ctx = RenderContext {
+            now: "2024-03-01T09:30:00Z"
+                .parse::<DateTime<chrono::Utc>>()
+                .unwrap()
+                .with_timezone(&Tz::UTC),
+            number: None,
+            post_count: None,
+        };
+        let credited = Question::from(Entry {
+            text: "Cats or dogs?".to_string(),
+            author: Some("Ana".to_string()),
+            ..Entry::default()
+        });
+        let anonymous = Question::new("Cats or dogs?".to_string());
+
+        let footer = |question| template.render(question, &ctx)["footer"]["text"].clone();
+        assert_eq!(footer(&credited), "Submitted by Ana");
+        assert_eq!(footer(&anonymous), "Asked at 09:30");
+    }
+
+    #[test]
+    fn source_fields_follow_the_category_up_to_the_cap() {
+        let template = EmbedTemplate {
+            show_category: true,
+            ..EmbedTemplate::default()
+        };
+        let ctx = RenderContext {
+            now: chrono::Utc::now().with_timezone(&Tz::UTC),
+            number: None,
+            post_count: None,
+        };
+        let fields = (0..30)
+            .map(|i| format!(r#"["Hint {}", "Clue {}", {}]"#, i, i, i == 0))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let raw = format!(
+            r#"{{"text": "Guess the city?", "category": "Travel", "fields": [{}]}}"#,
+            fields
+        );
+        let entry = SourceFormat::JsonLines
+            .parse(&raw, &PlainOptions::default())
+            .unwrap()
+            .remove(0);
+
+        let embed = template.render(&Question::from(entry), &ctx);
+        let fields = embed["fields"].as_array().unwrap();
+        assert_eq!(fields.len(), MAX_EMBED_FIELDS);
+        assert_eq!(fields[0]["name"], "Category");
+        assert_eq!(fields[0]["value"], "Travel");
+        assert_eq!(fields[1]["name"], "Hint 0");
+        assert_eq!(fields[1]["value"], "Clue 0");
+        assert_eq!(fields[1]["inline"], true);
+        assert_eq!(fields[2]["inline"], false);
+        assert_eq!(fields[24]["name"], "Hint 23");
+    }
+
+    #[test]
+    fn a_long_footer_shortens_the_description_at_a_word() {
+        let footer = "f".repeat(5000);
+        let mut embed = serde_json::json!({
+            "title": "Question of the day",
+            "description": "word ".repeat(400),
+            "footer": { "text": footer },
+        });
+
+        fit_embed(&mut embed).unwrap();
+        assert!(embed_length(&embed) <= MAX_EMBED_LENGTH);
+        assert_eq!(embed["footer"]["text"], footer);
+        let description = embed["description"].as_str().unwrap();
+        assert!(description.ends_with("word…"), "{}", description);
+
+        let mut too_long = serde_json::json!({
+            "description": "Short?",
+            "footer": { "text": "f".repeat(MAX_EMBED_LENGTH) },
+        });
+        assert!(fit_embed(&mut too_long).is_err());
+        assert_eq!(too_long["description"], "Short?");
+    }
+
+    #[test]
+    fn padding_adds_a_blank_line_only_when_enabled() {
+        let ctx = RenderContext {
+            now: chrono::Utc::now().with_timezone(&Tz::UTC),
+            number: None,
+            post_count: None,
+        };
+        let question = Question::new("Cats or dogs?".to_string());
+        let description = |padding| {
+            let template = EmbedTemplate {
+                padding,
+                ..EmbedTemplate::default()
+            };
+            template.render(&question, &ctx)["description"].clone()
+        };
+
+        assert_eq!(description(true), "Cats or dogs?\n\u{200B}");
+        assert_eq!(description(false), "Cats or dogs?");
+    }
+}
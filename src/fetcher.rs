@@ -0,0 +1,258 @@
+use async_trait::async_trait;
+use encoding_rs::{Encoding, UTF_8};
+use futures::AsyncReadExt;
+use isahc::http::{header::CONTENT_TYPE, HeaderMap};
+use std::{error::Error, fmt, future::Future, time::Duration};
+use tracing::warn;
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Where the raw question sources come from
+#[async_trait]
+pub trait SourceFetcher: Send + Sync {
+    async fn fetch(&self, url: &str) -> Result<String, Err>;
+}
+
+/// Fetches sources over HTTP
+#[derive(Debug)]
+pub struct IsahcFetcher {
+    pub policy: HttpPolicy,
+    /// Larger responses are rejected, they're most likely not a list of questions
+    pub max_size: u64,
+    /// Decode responses as their Content-Type or byte order mark says instead of as UTF-8
+    pub detect_encoding: bool,
+}
+
+/// A request that got no response in time, worth retrying on the next tick
+#[derive(Debug)]
+pub struct TimedOut {
+    pub target: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.target, self.after)
+    }
+}
+
+impl Error for TimedOut {}
+
+/// How long requests to the sources and Discord may take, and how often they're
+/// retried when they don't make it in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpPolicy {
+    pub timeout: Duration,
+    /// Retries of a request that timed out, 0 to fail right away
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every one after it
+    pub backoff: Duration,
+}
+
+impl Default for HttpPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            retries: 0,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl HttpPolicy {
+    /// Runs the request `call` makes until it finishes in time or the retries run out
+    pub async fn run<T, F, Fut>(&self, target: &str, mut call: F) -> Result<T, TimedOut>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let mut backoff = self.backoff;
+        for attempt in 0..=self.retries {
+            if let Ok(result) = tokio::time::timeout(self.timeout, call()).await {
+                return Ok(result);
+            }
+            if attempt < self.retries {
+                warn!(
+                    "{} timed out after {:?}, retrying in {:?}",
+                    target, self.timeout, backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        Err(TimedOut {
+            target: target.to_string(),
+            after: self.timeout,
+        })
+    }
+}
+
+impl IsahcFetcher {
+    pub fn new(policy: HttpPolicy, max_size: u64) -> Self {
+        Self {
+            policy,
+            max_size,
+            detect_encoding: false,
+        }
+    }
+
+    async fn fetch_once(&self, url: &str) -> Result<String, Err> {
+        let mut response = isahc::get_async(url).await?;
+
+        let too_large = || format!("{} is larger than {} bytes", url, self.max_size);
+        if response.body().len().is_some_and(|len| len > self.max_size) {
+            return Err(too_large().into());
+        }
+
+        // Without a Content-Length, read one byte past the limit to tell whether it was hit
+        let mut body = vec![];
+        response
+            .body_mut()
+            .take(self.max_size + 1)
+            .read_to_end(&mut body)
+            .await?;
+        if body.len() as u64 > self.max_size {
+            return Err(too_large().into());
+        }
+
+        self.decode(url, response.headers(), &body)
+    }
+
+    fn decode(&self, url: &str, headers: &HeaderMap, body: &[u8]) -> Result<String, Err> {
+        // Decoded like isahc's text(), as the Content-Type says with anything invalid replaced
+        if !self.detect_encoding {
+            let encoding = charset(headers)
+                .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+                .unwrap_or(UTF_8);
+            return Ok(encoding.decode(body).0.into_owned());
+        }
+
+        let encoding = detect_encoding(headers, body)?;
+        let (text, _, malformed) = encoding.decode(body);
+        if malformed {
+            return Err(format!("{} isn't valid {}", url, encoding.name()).into());
+        }
+
+        Ok(text.into_owned())
+    }
+}
+
+#[async_trait]
+impl SourceFetcher for IsahcFetcher {
+    async fn fetch(&self, url: &str) -> Result<String, Err> {
+        self.policy.run(url, || self.fetch_once(url)).await?
+    }
+}
+
+/// Picks the encoding from the charset of the Content-Type, then from a byte order mark,
+/// and otherwise goes with UTF-8
+pub fn detect_encoding(headers: &HeaderMap, body: &[u8]) -> Result<&'static Encoding, String> {
+    if let Some(charset) = charset(headers) {
+        return Encoding::for_label(charset.as_bytes())
+            .ok_or_else(|| format!("Unknown charset: {}", charset));
+    }
+
+    Ok(Encoding::for_bom(body).map_or(UTF_8, |(encoding, _)| encoding))
+}
+
+/// The charset parameter of the Content-Type
+fn charset(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value.split(';').find_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("charset")
+                    .then(|| value.trim().trim_matches('"'))
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn slow_calls_are_retried_then_time_out() {
+        let policy = HttpPolicy {
+            timeout: Duration::from_millis(10),
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+        let mut calls = 0;
+        let result = policy
+            .run("slow source", || {
+                calls += 1;
+                tokio::time::sleep(Duration::from_secs(5))
+            })
+            .await;
+
+        let timed_out = result.unwrap_err();
+        assert_eq!(timed_out.target, "slow source");
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn a_call_that_finishes_in_time_is_not_retried() {
+        let policy = HttpPolicy {
+            retries: 2,
+            ..HttpPolicy::default()
+        };
+        let mut calls = 0;
+        let result = policy
+            .run("fast source", || {
+                calls += 1;
+                async { "body" }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), "body");
+        assert_eq!(calls, 1);
+    }
+
+    fn content_type(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn detection_goes_by_the_charset_then_the_byte_order_mark() {
+        let mut fetcher = IsahcFetcher::new(HttpPolicy::default(), 1024);
+        fetcher.detect_encoding = true;
+        let plain = content_type("text/plain");
+        let decode = |headers: &HeaderMap, body: &[u8]| fetcher.decode("source", headers, body);
+
+        let latin1 = content_type("text/plain; charset=\"ISO-8859-1\"");
+        assert_eq!(
+            decode(&latin1, b"Caf\xe9 ou th\xe9 ?").unwrap(),
+            "Café ou thé ?"
+        );
+        assert_eq!(
+            decode(&plain, b"\xef\xbb\xbfCaf\xc3\xa9?").unwrap(),
+            "Café?"
+        );
+        assert_eq!(decode(&plain, b"\xff\xfeC\0a\0f\0\xe9\0").unwrap(), "Café");
+        assert_eq!(
+            decode(&HeaderMap::new(), "Café?".as_bytes()).unwrap(),
+            "Café?"
+        );
+
+        let e = decode(&plain, b"Caf\xe9?").unwrap_err();
+        assert_eq!(e.to_string(), "source isn't valid UTF-8");
+        let unknown = content_type("text/plain; charset=klingon");
+        assert!(decode(&unknown, b"Qapla'").is_err());
+    }
+
+    #[test]
+    fn without_detection_invalid_bytes_are_replaced() {
+        let fetcher = IsahcFetcher::new(HttpPolicy::default(), 1024);
+        let plain = content_type("text/plain");
+
+        let text = fetcher.decode("source", &plain, b"Caf\xe9?").unwrap();
+        assert_eq!(text, "Caf\u{FFFD}?");
+    }
+}
@@ -1,41 +1,688 @@
+mod audit;
 mod bot;
+mod command;
+mod control;
+mod discord;
+mod embed;
+mod fetcher;
+mod multi;
+#[cfg(feature = "s3")]
+mod s3;
+mod source;
+mod storage;
 
-use chrono::NaiveTime;
+use chrono::{NaiveDate, NaiveTime};
+use chrono_tz::Tz;
+use cron::Schedule;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 use structopt::StructOpt;
+use tokio::sync::mpsc;
 use tracing::info;
 
-use crate::bot::{Bot, Webhook};
+use crate::{
+    bot::{
+        parse_poll_duration, parse_schedule, Bot, CategoryCooldown, DateRange, DedupOptions,
+        DeduplicationAlgorithm, SelectionMode, Webhook,
+    },
+    command::Command,
+    control::ControlServer,
+    embed::{parse_color, parse_time_format, parse_url_template, CategoryColor, EmbedColor},
+    fetcher::{HttpPolicy, IsahcFetcher},
+    multi::{Config, EmbedConfig, MultiBot, QuestionSet},
+    source::{PlainOptions, SourceFormat, SourceSet},
+    storage::{JsonFileStorage, MemoryStorage},
+};
 
 type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
 
+// Switches that can also be set from the environment take a value, so they only accept it
+// after an `=`. Otherwise clap reads whatever follows the switch, like a subcommand, as its value.
+// They're read with `switch_on`, since a bare switch has no value but still turns it on.
 #[derive(Debug, StructOpt)]
 struct Args {
-    #[structopt(short = "c", long = "code", env = "QOTD_PASTEBIN")]
-    code: String,
-    #[structopt(short = "i", long = "id", env = "QOTD_WEBHOOK_ID")]
-    webhook_id: u64,
-    #[structopt(short = "t", long = "token", env = "QOTD_WEBHOOK_TOKEN")]
-    webhook_token: String,
+    /// Pastebin code or URL of a question source, can be given several times
+    #[structopt(
+        short = "c",
+        long = "code",
+        env = "QOTD_PASTEBIN",
+        required_unless_one = &["active-set", "config"],
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    code: Vec<String>,
+    /// Source of a named question set as `name=code`, can be given several times per set
+    #[structopt(
+        long = "set",
+        env = "QOTD_SETS",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    sets: Vec<SourceSet>,
+    /// Post from this set instead of --code. Each set keeps its own questions file, so
+    /// switching sets doesn't lose the progress of the others.
+    #[structopt(long = "active_set", env = "QOTD_ACTIVE_SET")]
+    active_set: Option<String>,
+    /// Id of the webhook questions are posted with
+    #[structopt(
+        short = "i",
+        long = "id",
+        env = "QOTD_WEBHOOK_ID",
+        required_unless = "config"
+    )]
+    webhook_id: Option<u64>,
+    /// Token of the webhook questions are posted with
+    #[structopt(
+        short = "t",
+        long = "token",
+        env = "QOTD_WEBHOOK_TOKEN",
+        required_unless = "config"
+    )]
+    webhook_token: Option<String>,
+    /// Time of day questions are posted at, in UTC
     #[structopt(long = "post_at", env = "QOTD_POST_AT", default_value = "12:00:00")]
     post_at: NaiveTime,
+    /// Cron expression to post on instead of daily at post_at, e.g. `0 9 * * 1-5`, or with
+    /// seconds as `0 0 9 * * Mon-Fri`. Evaluated in cron_timezone. Intros are only posted
+    /// with post_at.
+    #[structopt(
+        long = "cron",
+        alias = "schedule_cron",
+        env = "QOTD_CRON",
+        conflicts_with = "post-at",
+        parse(try_from_str = parse_schedule)
+    )]
+    cron: Option<Schedule>,
+    /// Timezone the cron expression is evaluated in, separate from the one times are shown in
+    #[structopt(
+        long = "cron_timezone",
+        env = "QOTD_CRON_TIMEZONE",
+        default_value = "UTC"
+    )]
+    cron_timezone: Tz,
+    /// How the sources are read, one question per line, one per feed item, one JSON object
+    /// per line, or CSV rows under a header
+    #[structopt(
+        long = "source_format",
+        env = "QOTD_SOURCE_FORMAT",
+        default_value = "plain",
+        possible_values = &["plain", "rss", "atom", "jsonl", "csv"]
+    )]
+    source_format: SourceFormat,
+    /// Skip lines in plain sources that start with this character
+    #[structopt(long = "source_comment_char", env = "QOTD_COMMENT_CHAR")]
+    source_comment_char: Option<char>,
+    /// Use `## Name` lines in plain sources as the category of the questions below them
+    #[structopt(
+        long = "section_comments_as_categories",
+        env = "QOTD_SECTION_CATEGORIES",
+        require_equals = true,
+        min_values = 0
+    )]
+    section_comments_as_categories: Option<Option<bool>>,
+    /// Timezone the time in the embed footer is shown in
+    #[structopt(
+        long = "footer_timezone",
+        env = "QOTD_FOOTER_TIMEZONE",
+        default_value = "UTC"
+    )]
+    footer_timezone: Tz,
+    /// Maximum number of seconds to wait before the first fetch after starting
+    #[structopt(
+        long = "startup_jitter",
+        env = "QOTD_STARTUP_JITTER",
+        default_value = "0"
+    )]
+    startup_jitter: u64,
+    /// Maximum number of seconds to wait before each source fetch, at most 30
+    #[structopt(
+        long = "source_poll_jitter_secs",
+        env = "QOTD_POLL_JITTER",
+        default_value = "0"
+    )]
+    source_poll_jitter_secs: u64,
+    /// Seconds the sources aren't fetched again after they last were, 0 to fetch every tick
+    #[structopt(
+        long = "cache_source_ttl_secs",
+        env = "QOTD_CACHE_TTL",
+        default_value = "0"
+    )]
+    cache_source_ttl_secs: i64,
+    /// Day nothing is posted on, like 2021-12-25, can be given several times
+    #[structopt(
+        long = "exclude_date",
+        env = "QOTD_EXCLUDE_DATES",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    exclude_dates: Vec<NaiveDate>,
+    /// Days nothing is posted on, like 2021-12-24/2021-12-26 with both ends included
+    #[structopt(
+        long = "exclude_date_range",
+        env = "QOTD_EXCLUDE_DATE_RANGES",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    exclude_date_ranges: Vec<DateRange>,
+    /// Embed colors of specific categories, as `category=#rrggbb`
+    #[structopt(
+        long = "category_colors",
+        env = "QOTD_CATEGORY_COLORS",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    category_colors: Vec<CategoryColor>,
+    /// Days that have to pass before a category is posted from again, as `category=days`
+    #[structopt(
+        long = "category_cooldown",
+        env = "QOTD_CATEGORY_COOLDOWNS",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    category_cooldowns: Vec<CategoryCooldown>,
+    /// Post a message with the number of questions when the bot starts
+    #[structopt(
+        long = "startup_announcement",
+        env = "QOTD_STARTUP_ANNOUNCE",
+        require_equals = true,
+        min_values = 0
+    )]
+    startup_announcement: Option<Option<bool>>,
+    /// Color of the startup announcement as hex
+    #[structopt(
+        long = "announcement_color",
+        env = "QOTD_ANNOUNCEMENT_COLOR",
+        parse(try_from_str = parse_color)
+    )]
+    announcement_color: Option<u32>,
+    /// Log the pool sizes and filters behind every question that is picked
+    #[structopt(
+        long = "explain",
+        env = "QOTD_EXPLAIN",
+        require_equals = true,
+        min_values = 0
+    )]
+    explain: Option<Option<bool>>,
+    /// Title of the question embed
+    #[structopt(long = "embed_title", env = "QOTD_EMBED_TITLE")]
+    embed_title: Option<String>,
+    /// Embed description, `{question}` is replaced with the question text
+    #[structopt(long = "embed_description", env = "QOTD_EMBED_DESCRIPTION")]
+    embed_description: Option<String>,
+    /// Questions longer than this many characters are shortened in the embed
+    #[structopt(
+        long = "max_embed_description_length",
+        env = "QOTD_MAX_EMBED_LENGTH",
+        default_value = "1024"
+    )]
+    max_embed_description_length: usize,
+    /// Embed color as hex, or `auto` to derive it from the question's category
+    #[structopt(long = "embed_color", env = "QOTD_EMBED_COLOR")]
+    embed_color: Option<EmbedColor>,
+    /// Color used with `--embed_color auto` for questions without a category
+    #[structopt(
+        long = "embed_fallback_color",
+        env = "QOTD_EMBED_FALLBACK_COLOR",
+        parse(try_from_str = parse_color)
+    )]
+    embed_fallback_color: Option<u32>,
+    /// Embed footer, `{time}` is replaced with the time of the post
+    #[structopt(long = "embed_footer", env = "QOTD_EMBED_FOOTER")]
+    embed_footer: Option<String>,
+    /// chrono format string of the `{time}` in the footer, like `%H:%M`
+    #[structopt(
+        long = "footer_time_format",
+        env = "QOTD_FOOTER_TIME_FORMAT",
+        parse(try_from_str = parse_time_format)
+    )]
+    footer_time_format: Option<String>,
+    /// Don't end question embeds with a blank line, whose zero-width space ends up in copied text
+    #[structopt(
+        long = "no_embed_padding",
+        env = "QOTD_NO_EMBED_PADDING",
+        require_equals = true,
+        min_values = 0
+    )]
+    no_embed_padding: Option<Option<bool>>,
+    /// Add the question's category to the embed as a field
+    #[structopt(
+        long = "embed_show_category",
+        env = "QOTD_EMBED_SHOW_CATEGORY",
+        require_equals = true,
+        min_values = 0
+    )]
+    embed_show_category: Option<Option<bool>>,
+    /// Thumbnail image of the embed, `{category}` is replaced with the question's category
+    #[structopt(
+        long = "embed_thumbnail_url",
+        env = "QOTD_THUMBNAIL_URL",
+        parse(try_from_str = parse_url_template)
+    )]
+    embed_thumbnail_url: Option<String>,
+    /// Link on the embed title, `{question_id}` and `{category}` are replaced with the id and
+    /// category of the posted question
+    #[structopt(long = "embed_url", env = "QOTD_EMBED_URL", parse(try_from_str = parse_url_template))]
+    embed_url: Option<String>,
+    /// Name shown above the embed title
+    #[structopt(long = "embed_author_name", env = "QOTD_EMBED_AUTHOR_NAME")]
+    embed_author_name: Option<String>,
+    /// Icon shown next to the author name
+    #[structopt(long = "embed_author_icon_url", env = "QOTD_EMBED_AUTHOR_ICON")]
+    embed_author_icon_url: Option<String>,
+    /// Show the question's number and the size of the pool in the footer
+    #[structopt(
+        long = "question_number_display",
+        env = "QOTD_SHOW_NUMBER",
+        require_equals = true,
+        min_values = 0
+    )]
+    question_number_display: Option<Option<bool>>,
+    /// Number the embed title with the count of questions posted so far
+    #[structopt(
+        long = "numbered",
+        env = "QOTD_NUMBERED",
+        require_equals = true,
+        min_values = 0
+    )]
+    numbered: Option<Option<bool>>,
+    /// Only merge source lines that exactly match a stored question, instead of near matches
+    #[structopt(
+        long = "exact_match",
+        env = "QOTD_EXACT_MATCH",
+        require_equals = true,
+        min_values = 0
+    )]
+    exact_match: Option<Option<bool>>,
+    /// Merge source lines within this edit distance of a stored question
+    #[structopt(
+        long = "dedup_threshold",
+        env = "QOTD_DEDUP_THRESHOLD",
+        default_value = "4"
+    )]
+    dedup_threshold: usize,
+    /// Merge source lines at least this similar (0.0 to 1.0) to a stored question
+    #[structopt(
+        long = "dedup_similarity_threshold",
+        env = "QOTD_DEDUP_SIMILARITY_THRESHOLD",
+        default_value = "0.85"
+    )]
+    dedup_similarity_threshold: f64,
+    /// Only merge source lines whose normalized text matches a stored question, skipping fuzzy matching
+    #[structopt(
+        long = "strict_dedup_only",
+        env = "QOTD_STRICT_DEDUP_ONLY",
+        require_equals = true,
+        min_values = 0
+    )]
+    strict_dedup_only: Option<Option<bool>>,
+    /// How near matches are scored: levenshtein, damerau_levenshtein, jaro_winkler or exact
+    #[structopt(
+        long = "dedup_algorithm",
+        env = "QOTD_DEDUP_ALGORITHM",
+        default_value = "damerau_levenshtein"
+    )]
+    dedup_algorithm: DeduplicationAlgorithm,
+    /// Fetch all sources at once instead of one after the other
+    #[structopt(
+        long = "concurrent_sources",
+        env = "QOTD_CONCURRENT_SOURCES",
+        require_equals = true,
+        min_values = 0
+    )]
+    concurrent_sources: Option<Option<bool>>,
+    /// Most sources fetched at the same time with --concurrent_sources
+    #[structopt(
+        long = "max_concurrent_fetches",
+        env = "QOTD_MAX_CONCURRENT_FETCHES",
+        default_value = "4"
+    )]
+    max_concurrent_fetches: usize,
+    /// Number of questions to post at once, fewer when not enough are left
+    #[structopt(
+        long = "questions_per_post",
+        env = "QOTD_QUESTIONS_PER_POST",
+        default_value = "1"
+    )]
+    questions_per_post: usize,
+    /// Pick from every question, including the ones posted already, for sets meant to repeat
+    #[structopt(
+        long = "allow_duplicate_posts",
+        env = "QOTD_ALLOW_DUPLICATE_POSTS",
+        require_equals = true,
+        min_values = 0
+    )]
+    allow_duplicate_posts: Option<Option<bool>>,
+    /// Post the unanswered questions by their sort order instead of shuffling them
+    #[structopt(
+        long = "sequential",
+        env = "QOTD_SEQUENTIAL",
+        conflicts_with = "allow-duplicate-posts",
+        require_equals = true,
+        min_values = 0
+    )]
+    sequential: Option<Option<bool>>,
+    /// How many hours the polls of questions with answer options stay open
+    #[structopt(
+        long = "poll_duration_hours",
+        env = "QOTD_POLL_DURATION",
+        default_value = "24",
+        parse(try_from_str = parse_poll_duration)
+    )]
+    poll_duration_hours: u16,
+    /// The webhook posts to a forum channel, so every question becomes a forum post
+    #[structopt(
+        long = "forum_channel",
+        env = "QOTD_FORUM_CHANNEL",
+        require_equals = true,
+        min_values = 0
+    )]
+    forum_channel: Option<Option<bool>>,
+    /// Ids of the forum tags to apply to the posts
+    #[structopt(
+        long = "forum_tags",
+        env = "QOTD_FORUM_TAGS",
+        use_delimiter = true,
+        number_of_values = 1,
+        requires = "forum-channel"
+    )]
+    forum_tags: Vec<u64>,
+    /// Id of a message posted by the webhook to replace with every question, so it can stay
+    /// pinned
+    #[structopt(
+        long = "edit_pinned",
+        env = "QOTD_EDIT_PINNED",
+        conflicts_with_all = &["create-thread", "forum-channel"]
+    )]
+    edit_pinned: Option<u64>,
+    /// Least milliseconds between two messages, so several questions in a row don't hit
+    /// Discord's rate limit
+    #[structopt(
+        long = "webhook_rate_limit_delay_ms",
+        env = "QOTD_WEBHOOK_DELAY",
+        default_value = "0"
+    )]
+    webhook_rate_limit_delay_ms: u64,
+    /// Start a discussion thread on every posted question
+    #[structopt(
+        long = "create_thread",
+        env = "QOTD_CREATE_THREAD",
+        requires = "bot-token",
+        require_equals = true,
+        min_values = 0
+    )]
+    create_thread: Option<Option<bool>>,
+    /// Bot token, needed for everything a webhook can't do on its own
+    #[structopt(long = "bot_token", env = "QOTD_BOT_TOKEN")]
+    bot_token: Option<String>,
+    /// Unsplash access key for fetching images for the embeds
+    #[structopt(long = "unsplash_api_key", env = "QOTD_UNSPLASH_KEY")]
+    unsplash_api_key: Option<String>,
+    /// Add an Unsplash photo matching the question's category to the embed
+    #[structopt(
+        long = "unsplash_query_from_category",
+        env = "QOTD_UNSPLASH_QUERY_FROM_CATEGORY",
+        requires = "unsplash-api-key",
+        require_equals = true,
+        min_values = 0
+    )]
+    unsplash_query_from_category: Option<Option<bool>>,
+    /// Shell command run before every post. Whatever it prints is posted as the question,
+    /// and when it prints nothing or fails the usual pick is posted.
+    #[structopt(long = "pre_post_command", env = "QOTD_PRE_POST_COMMAND")]
+    pre_post_command: Option<String>,
+    /// Seconds the pre-post command may run before it's killed
+    #[structopt(
+        long = "pre_post_command_timeout_secs",
+        env = "QOTD_PRE_POST_COMMAND_TIMEOUT",
+        default_value = "10"
+    )]
+    pre_post_command_timeout_secs: u64,
+    /// Number of audit log entries kept, the oldest are dropped first
+    #[structopt(
+        long = "audit_log_size",
+        env = "QOTD_AUDIT_LOG_SIZE",
+        default_value = "1000"
+    )]
+    audit_log_size: usize,
+    /// Only read this many questions from each source
+    #[structopt(
+        long = "max_source_lines",
+        env = "QOTD_MAX_SOURCE_LINES",
+        default_value = "10000"
+    )]
+    max_source_lines: usize,
+    /// Decode sources as the charset of their Content-Type or their byte order mark says,
+    /// instead of as UTF-8
+    #[structopt(
+        long = "source_encoding_detection",
+        env = "QOTD_SOURCE_ENCODING_DETECTION",
+        require_equals = true,
+        min_values = 0
+    )]
+    source_encoding_detection: Option<Option<bool>>,
+    /// Reject source responses larger than this many bytes
+    #[structopt(
+        long = "source_max_size_bytes",
+        env = "QOTD_MAX_SOURCE_BYTES",
+        default_value = "1048576"
+    )]
+    source_max_size_bytes: u64,
+    /// Skip source questions longer than this many characters
+    #[structopt(
+        long = "max_source_line_length",
+        env = "QOTD_MAX_SOURCE_LINE_LENGTH",
+        default_value = "4096"
+    )]
+    max_source_line_length: usize,
+    /// Don't fetch the sources while at least this many questions are unanswered, 0 always fetches
+    #[structopt(
+        long = "min_unanswered_before_load",
+        env = "QOTD_MIN_UNANSWERED",
+        default_value = "0"
+    )]
+    min_unanswered_before_load: usize,
+    /// Don't ask a question again within this many days of posting it
+    #[structopt(
+        long = "question_uniqueness_window_days",
+        env = "QOTD_UNIQUENESS_WINDOW_DAYS"
+    )]
+    question_uniqueness_window_days: Option<u32>,
+    /// Ask for more questions once fewer than this many are unanswered, 0 never asks
+    #[structopt(
+        long = "low_watermark",
+        env = "QOTD_LOW_WATERMARK",
+        default_value = "0"
+    )]
+    low_watermark: usize,
+    /// Number of answered questions to keep in the questions file, 0 keeps all of them
+    #[structopt(
+        long = "answered_questions_keep",
+        env = "QOTD_KEEP_ANSWERED",
+        default_value = "0"
+    )]
+    answered_questions_keep: usize,
+    /// Where the questions and the rest of the state are kept
+    #[structopt(
+        long = "questions_file",
+        env = "QOTD_QUESTIONS_FILE",
+        default_value = "questions.json"
+    )]
+    questions_file: PathBuf,
+    /// Write the questions file as indented JSON, which diffs well in version control
+    #[structopt(
+        long = "questions_json_pretty",
+        env = "QOTD_JSON_PRETTY",
+        require_equals = true,
+        min_values = 0
+    )]
+    questions_json_pretty: Option<Option<bool>>,
+    /// Keep the state in memory only, without reading or writing the questions file
+    #[structopt(
+        long = "no_persist",
+        env = "QOTD_NO_PERSIST",
+        require_equals = true,
+        min_values = 0
+    )]
+    no_persist: Option<Option<bool>>,
+    /// Post a teaser with the category this many seconds before the question, 0 to disable
+    #[structopt(
+        long = "intro_offset_secs",
+        env = "QOTD_INTRO_OFFSET",
+        default_value = "0"
+    )]
+    intro_offset_secs: i64,
+    /// Webhook that gets the three most reacted questions of the week on Mondays, counting
+    /// reactions needs --bot_token
+    #[structopt(
+        long = "summary_channel_webhook",
+        env = "QOTD_SUMMARY_WEBHOOK",
+        requires = "bot-token",
+        parse(try_from_str = Webhook::from_url)
+    )]
+    summary_channel_webhook: Option<Webhook>,
+    /// Hours after posting a question to count its reactions for the weekly summary
+    #[structopt(
+        long = "fetch_reactions_after_hours",
+        env = "QOTD_FETCH_REACTIONS_AFTER",
+        default_value = "24"
+    )]
+    fetch_reactions_after_hours: i64,
+    /// Day of the month to post a recap of the month's questions on, after the post time
+    #[structopt(
+        long = "monthly_recap_day",
+        env = "QOTD_MONTHLY_RECAP_DAY",
+        parse(try_from_str = parse_recap_day)
+    )]
+    monthly_recap_day: Option<u32>,
+    /// Languages of the `|||` separated translations in the source, in order
+    #[structopt(
+        long = "languages",
+        env = "QOTD_LANGUAGES",
+        use_delimiter = true,
+        number_of_values = 1
+    )]
+    languages: Vec<String>,
+    /// Which of the languages to post in, questions without that translation use the first one
+    #[structopt(long = "language", env = "QOTD_LANGUAGE", requires = "languages")]
+    language: Option<String>,
+    /// Separate webhook for startup announcements and operational warnings, which otherwise
+    /// go to the question channel, like https://discord.com/api/webhooks/<id>/<token>
+    #[structopt(
+        long = "notification_webhook_url",
+        env = "QOTD_NOTIFY_WEBHOOK",
+        parse(try_from_str = Webhook::from_url)
+    )]
+    notification_webhook_url: Option<Webhook>,
+    /// Port of an HTTP endpoint for posting on demand with `POST /post`
+    #[structopt(
+        long = "control_port",
+        env = "QOTD_CONTROL_PORT",
+        requires = "control-token"
+    )]
+    control_port: Option<u16>,
+    /// Address the control endpoint listens on, 0.0.0.0 to accept requests from other hosts
+    #[structopt(
+        long = "control_host",
+        env = "QOTD_CONTROL_HOST",
+        default_value = "127.0.0.1"
+    )]
+    control_host: IpAddr,
+    /// Bearer token the control endpoint requires
+    #[structopt(long = "control_token", env = "QOTD_CONTROL_TOKEN")]
+    control_token: Option<String>,
+    /// Seconds a request to a source or Discord may take before it fails
+    #[structopt(long = "http_timeout", env = "QOTD_HTTP_TIMEOUT", default_value = "30")]
+    http_timeout: u64,
+    /// How often a request to a source or Discord that timed out is retried
+    #[structopt(long = "http_retries", env = "QOTD_HTTP_RETRIES", default_value = "0")]
+    http_retries: u32,
+    /// Seconds to wait before the first retry, doubled for every one after it
+    #[structopt(
+        long = "http_backoff_secs",
+        env = "QOTD_HTTP_BACKOFF",
+        default_value = "1"
+    )]
+    http_backoff_secs: u64,
+    /// TOML file with a `[[set]]` per question pool to run side by side, instead of --code,
+    /// --id and --token. Every other option applies to all of them, and an `[embed]` section
+    /// lays out their embeds.
+    #[structopt(long = "config", env = "QOTD_CONFIG")]
+    config: Option<PathBuf>,
+    /// S3 bucket of a question source, read with the AWS credentials of the environment
+    #[cfg(feature = "s3")]
+    #[structopt(long = "s3_bucket", env = "QOTD_S3_BUCKET", requires = "s3-key")]
+    s3_bucket: Option<String>,
+    /// Key of the question source in --s3_bucket
+    #[cfg(feature = "s3")]
+    #[structopt(long = "s3_key", env = "QOTD_S3_KEY", requires = "s3-bucket")]
+    s3_key: Option<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
 #[paw::main]
 #[tokio::main]
-async fn main(args: Args) -> Result<(), Err> {
+async fn main(mut args: Args) -> Result<(), Err> {
     tracing_subscriber::fmt()
         .with_env_filter("qotd=debug")
         .init();
 
-    let mut bot = Bot::new(
-        args.code,
-        Webhook::new(args.webhook_id, args.webhook_token),
-        args.post_at,
-    );
+    let command = args.command.take();
+    let config = match &args.config {
+        Some(path) => Config::read(path).await?,
+        None => Config {
+            set: vec![cli_set(&args)?],
+            embed: EmbedConfig::default(),
+        },
+    };
+    let mut bots = config
+        .set
+        .into_iter()
+        .map(|set| Ok((set.name.clone(), build_bot(&args, set, &config.embed)?)))
+        .collect::<Result<Vec<(String, Bot)>, Err>>()?;
+
+    if let Some(command) = command {
+        return match &mut bots[..] {
+            [(_, bot)] => command.run(bot).await,
+            _ => Err("Subcommands work on a single question set, run them without --config".into()),
+        };
+    }
+
+    let control = match (args.control_port, args.control_token) {
+        (Some(port), Some(token)) => {
+            let mut sets = HashMap::new();
+            for (name, bot) in &mut bots {
+                let (posts, manual_posts) = mpsc::channel(1);
+                bot.manual_posts = Some(manual_posts);
+                sets.insert(name.clone(), posts);
+            }
+            Some(ControlServer {
+                host: args.control_host,
+                port,
+                token,
+                sets,
+            })
+        }
+        _ => None,
+    };
+    let control = async {
+        match control {
+            Some(control) => control.serve().await,
+            None => futures::future::pending().await,
+        }
+    };
 
     tokio::select! {
-        err = bot.start() => {
-            info!("Bot stopped: {:?}", err);
+        _ = MultiBot { bots }.start() => {
+            info!("All bots stopped");
+        }
+        result = control => {
+            info!("Control endpoint stopped: {:?}", result);
         }
         _ = tokio::signal::ctrl_c() => {
             info!("Ctrl-C received, stopping bot");
@@ -44,3 +691,270 @@ async fn main(args: Args) -> Result<(), Err> {
 
     Ok(())
 }
+
+/// The single question set configured through the command line
+fn cli_set(args: &Args) -> Result<QuestionSet, Err> {
+    let mut questions_file = args.questions_file.clone();
+    let code = match &args.active_set {
+        Some(active) => {
+            let codes = args
+                .sets
+                .iter()
+                .filter(|set| set.name == *active)
+                .map(|set| set.code.clone())
+                .collect::<Vec<String>>();
+            if codes.is_empty() {
+                return Err(format!("No sources in question set {}", active).into());
+            }
+
+            questions_file = set_questions_file(&questions_file, active);
+            codes
+        }
+        None => args.code.clone(),
+    };
+    #[cfg(feature = "s3")]
+    let code = {
+        let mut code = code;
+        if let (Some(bucket), Some(key)) = (&args.s3_bucket, &args.s3_key) {
+            code.push(format!("s3://{}/{}", bucket, key));
+        }
+        code
+    };
+
+    Ok(QuestionSet {
+        name: args
+            .active_set
+            .clone()
+            .unwrap_or_else(|| "default".to_string()),
+        code,
+        webhook_id: args.webhook_id.ok_or("--id is required")?,
+        webhook_token: args.webhook_token.clone().ok_or("--token is required")?,
+        post_at: Some(args.post_at),
+        questions_file: Some(questions_file),
+    })
+}
+
+/// Sets up the bot of a question set, applying every other option to it
+fn build_bot(args: &Args, set: QuestionSet, embed: &EmbedConfig) -> Result<Bot, Err> {
+    let mut hook = Webhook::new(set.webhook_id, set.webhook_token);
+    hook.footer_timezone = args.footer_timezone;
+    hook.create_thread = switch_on(args.create_thread);
+    hook.edit_pinned = args.edit_pinned;
+    hook.rate_limit_delay = Duration::from_millis(args.webhook_rate_limit_delay_ms);
+    hook.forum = switch_on(args.forum_channel);
+    hook.poll_duration_hours = args.poll_duration_hours;
+    hook.forum_tags = args.forum_tags.clone();
+    hook.bot_token = args.bot_token.clone();
+    let policy = HttpPolicy {
+        timeout: Duration::from_secs(args.http_timeout),
+        retries: args.http_retries,
+        backoff: Duration::from_secs(args.http_backoff_secs),
+    };
+    hook.http_policy = policy;
+
+    let questions_file = set
+        .questions_file
+        .unwrap_or_else(|| set_questions_file(&args.questions_file, &set.name));
+
+    let mut bot = Bot::new(set.code, hook, set.post_at.unwrap_or(args.post_at));
+    bot.notification_hook = args.notification_webhook_url.clone().map(|mut hook| {
+        hook.http_policy = policy;
+        hook
+    });
+    let mut fetcher = IsahcFetcher::new(policy, args.source_max_size_bytes);
+    fetcher.detect_encoding = switch_on(args.source_encoding_detection);
+    #[cfg(feature = "s3")]
+    let fetcher = s3::S3Fetcher::new(fetcher);
+    bot.fetcher = Arc::new(fetcher);
+    bot.schedule = args.cron.clone();
+    bot.timezone = args.cron_timezone;
+    bot.source_format = args.source_format;
+    bot.plain_options = PlainOptions {
+        comment_char: args.source_comment_char,
+        section_categories: switch_on(args.section_comments_as_categories),
+    };
+    bot.startup_jitter = Duration::from_secs(args.startup_jitter);
+    bot.source_poll_jitter = Duration::from_secs(args.source_poll_jitter_secs);
+    bot.cache_source_ttl = chrono::Duration::seconds(args.cache_source_ttl_secs);
+    bot.exclude_dates = args.exclude_dates.clone();
+    bot.exclude_date_ranges = args.exclude_date_ranges.clone();
+    bot.category_cooldowns = args.category_cooldowns.clone();
+    bot.explain = switch_on(args.explain);
+    bot.questions_per_post = args.questions_per_post;
+    if switch_on(args.allow_duplicate_posts) {
+        bot.selection_mode = SelectionMode::AllowRepeats;
+    }
+    if switch_on(args.sequential) {
+        bot.selection_mode = SelectionMode::Sequential;
+    }
+    bot.startup_announcement = switch_on(args.startup_announcement);
+    bot.dedup = DedupOptions {
+        exact: switch_on(args.exact_match),
+        threshold: args.dedup_threshold,
+        similarity_threshold: args.dedup_similarity_threshold,
+        strict_only: switch_on(args.strict_dedup_only),
+        algorithm: args.dedup_algorithm,
+    };
+
+    bot.concurrent_sources = switch_on(args.concurrent_sources);
+    bot.max_concurrent_fetches = args.max_concurrent_fetches;
+    bot.unsplash_api_key = args.unsplash_api_key.clone();
+    bot.unsplash_query_from_category = switch_on(args.unsplash_query_from_category);
+    bot.pre_post_command = args.pre_post_command.clone();
+    bot.pre_post_command_timeout = Duration::from_secs(args.pre_post_command_timeout_secs);
+    bot.audit_log_size = args.audit_log_size;
+    bot.max_source_lines = args.max_source_lines;
+    bot.max_source_line_length = args.max_source_line_length;
+    bot.min_unanswered_before_load = args.min_unanswered_before_load;
+    bot.uniqueness_window_days = args.question_uniqueness_window_days;
+    bot.low_watermark = args.low_watermark;
+    bot.answered_questions_keep = args.answered_questions_keep;
+    bot.monthly_recap_day = args.monthly_recap_day;
+    bot.summary_hook = args.summary_channel_webhook.clone().map(|mut hook| {
+        hook.http_policy = policy;
+        hook
+    });
+    bot.fetch_reactions_after = chrono::Duration::hours(args.fetch_reactions_after_hours);
+    bot.intro_offset = chrono::Duration::seconds(args.intro_offset_secs);
+    bot.storage = match switch_on(args.no_persist) {
+        true => Box::new(MemoryStorage::default()),
+        false => {
+            let mut storage = JsonFileStorage::new(questions_file);
+            storage.pretty = switch_on(args.questions_json_pretty);
+            Box::new(storage)
+        }
+    };
+
+    let template = &mut bot.template;
+    embed.apply(template)?;
+    if let Some(title) = &args.embed_title {
+        template.title = title.clone();
+    }
+    if let Some(description) = &args.embed_description {
+        template.description_template = description.clone();
+    }
+    template.max_description_length = args.max_embed_description_length;
+    if let Some(color) = args.embed_color {
+        template.color = color;
+    }
+    template.category_colors = args.category_colors.clone();
+    if let Some(color) = args.embed_fallback_color {
+        template.fallback_color = color;
+    }
+    if let Some(color) = args.announcement_color {
+        template.announcement_color = color;
+    }
+    if let Some(footer) = &args.embed_footer {
+        template.footer_template = footer.clone();
+    }
+    if let Some(time_format) = &args.footer_time_format {
+        template.time_format = time_format.clone();
+    }
+    if args.embed_show_category.is_some() {
+        template.show_category = switch_on(args.embed_show_category);
+    }
+    template.padding = !switch_on(args.no_embed_padding);
+    if args.question_number_display.is_some() {
+        template.show_question_number = switch_on(args.question_number_display);
+    }
+    template.show_post_count = switch_on(args.numbered);
+    if let Some(url) = &args.embed_thumbnail_url {
+        template.thumbnail_url = Some(url.clone());
+    }
+    if let Some(url) = &args.embed_url {
+        template.url = Some(url.clone());
+    }
+    if let Some(name) = &args.embed_author_name {
+        template.author_name = Some(name.clone());
+    }
+    if let Some(url) = &args.embed_author_icon_url {
+        template.author_icon_url = Some(url.clone());
+    }
+    if let Some(language) = &args.language {
+        template.variant = args
+            .languages
+            .iter()
+            .position(|l| l.eq_ignore_ascii_case(language))
+            .ok_or_else(|| format!("Language {} is not one of {:?}", language, args.languages))?;
+    }
+
+    Ok(bot)
+}
+
+/// Recaps are limited to the days every month has
+fn parse_recap_day(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(day @ 1..=28) => Ok(day),
+        _ => Err(format!("Recap day must be 1 to 28, got {}", s)),
+    }
+}
+
+/// Questions file of a named set, `questions.json` becoming `questions.winter.json`
+fn set_questions_file(path: &Path, set: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, set, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, set),
+    };
+
+    path.with_file_name(file_name)
+}
+
+/// Whether a switch was given, bare or as `=true`
+fn switch_on(switch: Option<Option<bool>>) -> bool {
+    switch.is_some_and(|value| value.unwrap_or(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(questions_file: &Path, extra: &[&str]) -> Args {
+        let questions_file = questions_file.to_string_lossy();
+        let required = ["qotd", "--code", "abc", "--id", "1", "--token", "t"];
+        let questions_file = ["--questions_file", &questions_file];
+        Args::from_iter_safe(required.iter().chain(&questions_file).chain(extra)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn no_persist_keeps_the_state_in_memory() {
+        let path = std::env::temp_dir().join(format!("qotd-{}.json", uuid::Uuid::new_v4()));
+        for (extra, persisted) in [
+            (&["--no_persist"][..], false),
+            (&["--no_persist=false"][..], true),
+            (&[][..], true),
+        ] {
+            let args = args(&path, extra);
+            let set = cli_set(&args).unwrap();
+            let mut bot = build_bot(&args, set, &EmbedConfig::default()).unwrap();
+            bot.restore().await.unwrap();
+            bot.submit("Still remembered?".to_string(), None);
+            bot.save().await.unwrap();
+            bot.restore().await.unwrap();
+
+            assert_eq!(bot.state.questions.len(), 1, "{:?}", extra);
+            assert_eq!(path.exists(), persisted, "{:?}", extra);
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[test]
+    fn embed_flags_take_precedence_over_the_config() {
+        let embed = EmbedConfig {
+            title: Some("From the config".to_string()),
+            footer: Some("Config footer".to_string()),
+            show_category: Some(true),
+            ..EmbedConfig::default()
+        };
+        let path = Path::new("questions.json");
+        let flags = args(path, &["--embed_title", "From a flag"]);
+        let bot = build_bot(&flags, cli_set(&flags).unwrap(), &embed).unwrap();
+        assert_eq!(bot.template.title, "From a flag");
+        assert_eq!(bot.template.footer_template, "Config footer");
+        assert!(bot.template.show_category);
+
+        let flags = args(path, &["--embed_show_category=false"]);
+        let bot = build_bot(&flags, cli_set(&flags).unwrap(), &embed).unwrap();
+        assert!(!bot.template.show_category);
+    }
+}
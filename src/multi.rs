@@ -0,0 +1,180 @@
+use chrono::NaiveTime;
+use futures::future::join_all;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::{
+    bot::Bot,
+    embed::{parse_url_template, EmbedTemplate},
+};
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A pool of questions posted independently of the others, listed as `[[set]]` in the config file
+#[derive(Debug, Deserialize)]
+pub struct QuestionSet {
+    pub name: String,
+    /// Pastebin codes or URLs of the set's sources
+    pub code: Vec<String>,
+    pub webhook_id: u64,
+    pub webhook_token: String,
+    /// Defaults to --post_at
+    pub post_at: Option<NaiveTime>,
+    /// Defaults to --questions_file with the set's name added
+    pub questions_file: Option<PathBuf>,
+}
+
+/// The `[embed]` section, the layout of every set's embeds. The --embed_* flags take precedence.
+#[derive(Debug, Default, Deserialize)]
+pub struct EmbedConfig {
+    pub title: Option<String>,
+    /// `{question}` is replaced with the question text
+    pub description: Option<String>,
+    /// Hex, or `auto` to derive it from the question's category
+    pub color: Option<String>,
+    /// `{time}` is replaced with the time of the post
+    pub footer: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub url: Option<String>,
+    pub author_name: Option<String>,
+    pub author_icon_url: Option<String>,
+    pub show_category: Option<bool>,
+    pub show_question_number: Option<bool>,
+}
+
+impl EmbedConfig {
+    /// Sets what the section sets, leaving the rest of `template` as it was
+    pub fn apply(&self, template: &mut EmbedTemplate) -> Result<(), Err> {
+        if let Some(title) = &self.title {
+            template.title = title.clone();
+        }
+        if let Some(description) = &self.description {
+            template.description_template = description.clone();
+        }
+        if let Some(color) = &self.color {
+            template.color = color.parse()?;
+        }
+        if let Some(footer) = &self.footer {
+            template.footer_template = footer.clone();
+        }
+        if let Some(url) = &self.thumbnail_url {
+            template.thumbnail_url = Some(parse_url_template(url)?);
+        }
+        if let Some(url) = &self.url {
+            template.url = Some(parse_url_template(url)?);
+        }
+        if let Some(name) = &self.author_name {
+            template.author_name = Some(name.clone());
+        }
+        if let Some(url) = &self.author_icon_url {
+            template.author_icon_url = Some(url.clone());
+        }
+        if let Some(show) = self.show_category {
+            template.show_category = show;
+        }
+        if let Some(show) = self.show_question_number {
+            template.show_question_number = show;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub set: Vec<QuestionSet>,
+    #[serde(default)]
+    pub embed: EmbedConfig,
+}
+
+impl Config {
+    pub async fn read(path: &Path) -> Result<Self, Err> {
+        let contents = tokio::fs::read_to_string(path).await?;
+        let config = toml::from_str::<Config>(&contents)?;
+        if config.set.is_empty() {
+            return Err(format!("{} has no [[set]]", path.display()).into());
+        }
+
+        Ok(config)
+    }
+}
+
+/// Runs the bot of every question set in a task of its own
+pub struct MultiBot {
+    pub bots: Vec<(String, Bot)>,
+}
+
+impl MultiBot {
+    /// Runs until every set has stopped, a set stopping on an error leaves the others running
+    pub async fn start(self) {
+        let tasks = self.bots.into_iter().map(|(name, mut bot)| {
+            tokio::spawn(async move {
+                let result = bot.start().await;
+                (name, result)
+            })
+        });
+
+        for task in join_all(tasks).await {
+            match task {
+                Ok((name, result)) => info!("Bot for {} stopped: {:?}", name, result),
+                Err(e) => warn!("Bot task failed: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed::EmbedColor;
+
+    const CONFIG: &str = r#"
+[embed]
+title = "Daily question"
+color = "auto"
+thumbnail_url = "https://example.com/{category}.png"
+show_category = true
+
+[[set]]
+name = "main"
+code = ["abc"]
+webhook_id = 1
+webhook_token = "t"
+"#;
+
+    #[test]
+    fn embed_section_overrides_only_what_it_sets() {
+        let config = toml::from_str::<Config>(CONFIG).unwrap();
+        let mut template = EmbedTemplate::default();
+        config.embed.apply(&mut template).unwrap();
+
+        assert_eq!(template.title, "Daily question");
+        assert!(matches!(template.color, EmbedColor::Automatic));
+        assert_eq!(
+            template.thumbnail_url.as_deref(),
+            Some("https://example.com/{category}.png")
+        );
+        assert!(template.show_category);
+        assert!(!template.show_question_number);
+        assert_eq!(
+            template.footer_template,
+            EmbedTemplate::default().footer_template
+        );
+    }
+
+    #[test]
+    fn embed_section_is_validated_like_the_flags() {
+        let config = toml::from_str::<Config>(
+            "[embed]\nurl = \"http://example.com\"\n[[set]]\nname = \"main\"\ncode = []\nwebhook_id = 1\nwebhook_token = \"t\"",
+        )
+        .unwrap();
+
+        let e = config
+            .embed
+            .apply(&mut EmbedTemplate::default())
+            .unwrap_err();
+        assert_eq!(e.to_string(), "URL must use https: http://example.com");
+    }
+}
@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use aws_sdk_s3::{primitives::ByteStream, Client};
+use tokio::sync::OnceCell;
+
+use crate::fetcher::{IsahcFetcher, SourceFetcher};
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Where S3 objects are read from
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// The object's length, if the store sent one, and its body
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<(Option<u64>, ByteStream), Err>;
+}
+
+/// S3 itself, with a client created from the environment on first use
+#[derive(Debug, Default)]
+pub struct AwsStore(OnceCell<Client>);
+
+#[async_trait]
+impl ObjectStore for AwsStore {
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<(Option<u64>, ByteStream), Err> {
+        let object = self
+            .0
+            .get_or_init(|| async { Client::new(&aws_config::load_from_env().await) })
+            .await
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?;
+
+        Ok((object.content_length().map(|len| len as u64), object.body))
+    }
+}
+
+/// Fetches `s3://bucket/key` sources with the credentials of the environment, and
+/// everything else over HTTP. Both go by the timeout and size limit of `http`.
+pub struct S3Fetcher {
+    store: Box<dyn ObjectStore>,
+    http: IsahcFetcher,
+}
+
+impl S3Fetcher {
+    pub fn new(http: IsahcFetcher) -> Self {
+        Self {
+            store: Box::new(AwsStore::default()),
+            http,
+        }
+    }
+
+    async fn fetch_object(&self, bucket: &str, key: &str, url: &str) -> Result<String, Err> {
+        let (content_length, mut body_stream) = self.store.get_object(bucket, key).await?;
+
+        let max_size = self.http.max_size;
+        let too_large = || format!("{} is larger than {} bytes", url, max_size);
+        if content_length.is_some_and(|len| len > max_size) {
+            return Err(too_large().into());
+        }
+
+        // The length is optional, so the limit is enforced while reading as well
+        let mut body = vec![];
+        while let Some(chunk) = body_stream.next().await {
+            body.extend_from_slice(&chunk?);
+            if body.len() as u64 > max_size {
+                return Err(too_large().into());
+            }
+        }
+
+        Ok(String::from_utf8(body)?)
+    }
+}
+
+#[async_trait]
+impl SourceFetcher for S3Fetcher {
+    async fn fetch(&self, url: &str) -> Result<String, Err> {
+        let (bucket, key) = match url.strip_prefix("s3://") {
+            Some(path) => path
+                .split_once('/')
+                .ok_or_else(|| format!("Expected s3://bucket/key, got {}", url))?,
+            None => return self.http.fetch(url).await,
+        };
+
+        self.http
+            .policy
+            .run(url, || self.fetch_object(bucket, key, url))
+            .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::{HttpPolicy, TimedOut};
+    use std::time::Duration;
+
+    /// Serves one object for every key, claiming `content_length` for it. Hangs when there's
+    /// no body.
+    struct MockStore {
+        content_length: Option<u64>,
+        body: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl ObjectStore for MockStore {
+        async fn get_object(&self, _: &str, _: &str) -> Result<(Option<u64>, ByteStream), Err> {
+            match self.body {
+                Some(body) => Ok((
+                    self.content_length,
+                    ByteStream::from_static(body.as_bytes()),
+                )),
+                None => std::future::pending().await,
+            }
+        }
+    }
+
+    fn fetcher(content_length: Option<u64>, body: Option<&'static str>) -> S3Fetcher {
+        let policy = HttpPolicy {
+            timeout: Duration::from_millis(20),
+            ..HttpPolicy::default()
+        };
+        S3Fetcher {
+            store: Box::new(MockStore {
+                content_length,
+                body,
+            }),
+            http: IsahcFetcher::new(policy, 10),
+        }
+    }
+
+    #[tokio::test]
+    async fn objects_within_the_limit_are_read() {
+        let body = fetcher(Some(10), Some("Why not?\n"))
+            .fetch("s3://bucket/questions.txt")
+            .await
+            .unwrap();
+        assert_eq!(body, "Why not?\n");
+    }
+
+    #[tokio::test]
+    async fn objects_over_the_limit_are_rejected_with_or_without_a_length() {
+        let claimed = fetcher(Some(11), Some("Why not?\n"))
+            .fetch("s3://bucket/key")
+            .await;
+        assert!(claimed
+            .unwrap_err()
+            .to_string()
+            .contains("larger than 10 bytes"));
+
+        let streamed = fetcher(None, Some("Why not? Why?\n"))
+            .fetch("s3://bucket/key")
+            .await;
+        assert!(streamed
+            .unwrap_err()
+            .to_string()
+            .contains("larger than 10 bytes"));
+    }
+
+    #[tokio::test]
+    async fn a_hanging_store_times_out() {
+        let result = fetcher(None, None).fetch("s3://bucket/key").await;
+        assert!(result.unwrap_err().is::<TimedOut>());
+    }
+
+    #[tokio::test]
+    async fn urls_without_a_key_are_rejected() {
+        let result = fetcher(None, Some("")).fetch("s3://bucket").await;
+        assert!(result.unwrap_err().to_string().contains("s3://bucket/key"));
+    }
+}
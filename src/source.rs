@@ -0,0 +1,513 @@
+use serde::Deserialize;
+use std::str::FromStr;
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    /// One question per line
+    Plain,
+    /// RSS 2.0 feed, one question per item
+    Rss,
+    /// Atom feed, one question per entry
+    Atom,
+    /// One JSON object per line, with the fields of an entry
+    JsonLines,
+    /// Comma separated with a header row, naming the columns `text`, `author`, `category`
+    /// and `fields`
+    Csv,
+}
+
+/// A question as read from the source, before it is merged into the stored questions
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Entry {
+    pub text: String,
+    pub author: Option<String>,
+    pub category: Option<String>,
+    pub poll_options: Vec<String>,
+    pub pinned: bool,
+    /// The question in other languages, in the order the source lists them
+    pub variants: Vec<String>,
+    /// Extra embed fields as `[name, value, inline]`, only set by the structured formats
+    #[serde(rename = "fields")]
+    pub custom_fields: Vec<(String, String, bool)>,
+}
+
+/// A CSV row, which has no lists of its own so the fields come as a JSON array
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    text: String,
+    author: Option<String>,
+    category: Option<String>,
+    fields: Option<String>,
+}
+
+/// How plain sources are read, ignored by the feed formats
+#[derive(Debug, Default)]
+pub struct PlainOptions {
+    /// Lines starting with this are skipped
+    pub comment_char: Option<char>,
+    /// `## Name` lines set the category of the questions below them
+    pub section_categories: bool,
+}
+
+/// A source that belongs to a named set of questions, written as `name=code`
+#[derive(Debug, Clone)]
+pub struct SourceSet {
+    pub name: String,
+    pub code: String,
+}
+
+impl FromStr for SourceSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, code)) if !name.trim().is_empty() && !code.trim().is_empty() => Ok(Self {
+                name: name.trim().to_string(),
+                code: code.trim().to_string(),
+            }),
+            _ => Err(format!("Expected a set like winter=abc123, got {}", s)),
+        }
+    }
+}
+
+impl SourceFormat {
+    pub fn parse(&self, raw: &str, plain: &PlainOptions) -> Result<Vec<Entry>, Err> {
+        match self {
+            SourceFormat::Plain => Ok(parse_plain(raw, plain)),
+            SourceFormat::Rss => parse_rss(raw),
+            SourceFormat::Atom => parse_atom(raw),
+            SourceFormat::JsonLines => parse_json_lines(raw),
+            SourceFormat::Csv => parse_csv(raw),
+        }
+    }
+}
+
+impl FromStr for SourceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plain" => Ok(SourceFormat::Plain),
+            "rss" => Ok(SourceFormat::Rss),
+            "atom" => Ok(SourceFormat::Atom),
+            "jsonl" => Ok(SourceFormat::JsonLines),
+            "csv" => Ok(SourceFormat::Csv),
+            _ => Err(format!("Unknown source format: {}", s)),
+        }
+    }
+}
+
+fn parse_plain(raw: &str, options: &PlainOptions) -> Vec<Entry> {
+    let mut section = None;
+    let mut entries = vec![];
+    for line in raw.split('\n').map(|line| line.trim()) {
+        if line.is_empty() {
+            continue;
+        }
+
+        if let (true, Some(name)) = (options.section_categories, line.strip_prefix("##")) {
+            section = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+            continue;
+        }
+
+        if options.comment_char.is_some_and(|c| line.starts_with(c)) {
+            continue;
+        }
+
+        let mut entry = parse_plain_line(line);
+        if entry.category.is_none() {
+            entry.category = section.clone();
+        }
+        entries.push(entry);
+    }
+
+    entries
+}
+
+/// Parses a single plain line, splitting `Question? | Option A | Option B` into a poll.
+/// A trailing `!pinned` keeps the question eligible after it has been posted, and a
+/// trailing `-- @name` credits whoever submitted the question. Translations follow the
+/// question separated by `|||`, as in `English? ||| Français ?`.
+fn parse_plain_line(line: &str) -> Entry {
+    let (line, pinned) = match line.strip_suffix("!pinned") {
+        Some(rest) => (rest.trim_end(), true),
+        None => (line, false),
+    };
+
+    // The @ tells the credit apart from dashes in the question itself
+    let (line, author) = match line.rsplit_once(" -- @") {
+        Some((rest, name)) if !rest.trim().is_empty() && !name.trim().is_empty() => {
+            (rest.trim_end(), Some(name.trim().to_string()))
+        }
+        _ => (line, None),
+    };
+
+    let mut variants = line.split("|||").map(|variant| variant.trim());
+    let line = variants.next().unwrap_or_default();
+    let variants = variants
+        .filter(|variant| !variant.is_empty())
+        .collect::<Vec<&str>>();
+
+    let mut parts = line.split('|').map(|part| part.trim());
+    let text = parts.next().unwrap_or_default();
+    let poll_options = parts
+        .filter(|option| !option.is_empty())
+        .map(|option| option.to_string())
+        .collect::<Vec<String>>();
+
+    // A poll needs at least two options, otherwise the pipe is part of the question
+    if text.is_empty() || poll_options.len() < 2 {
+        return Entry {
+            text: line.to_string(),
+            author,
+            pinned,
+            variants: variants.iter().map(|variant| variant.to_string()).collect(),
+            ..Default::default()
+        };
+    }
+
+    // Translated polls share the options of the first language
+    let variants = variants
+        .iter()
+        .map(|variant| {
+            variant
+                .split('|')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .collect();
+
+    Entry {
+        text: text.to_string(),
+        author,
+        poll_options,
+        pinned,
+        variants,
+        ..Default::default()
+    }
+}
+
+impl Entry {
+    /// Writes the entry as a plain line that parses back to it, categories and fields aside
+    pub fn to_plain_line(&self) -> String {
+        let mut line = self.text.clone();
+        for option in &self.poll_options {
+            line.push_str(" | ");
+            line.push_str(option);
+        }
+        for variant in &self.variants {
+            line.push_str(" ||| ");
+            line.push_str(variant);
+        }
+        if let Some(author) = &self.author {
+            line.push_str(" -- @");
+            line.push_str(author);
+        }
+        if self.pinned {
+            line.push_str(" !pinned");
+        }
+
+        line
+    }
+}
+
+fn parse_rss(raw: &str) -> Result<Vec<Entry>, Err> {
+    let channel = rss::Channel::read_from(raw.as_bytes())?;
+
+    Ok(channel
+        .items()
+        .iter()
+        .filter_map(|item| {
+            let text = item.title()?.trim();
+            if text.is_empty() {
+                return None;
+            }
+
+            Some(Entry {
+                text: text.to_string(),
+                author: item.author().map(|a| a.trim().to_string()),
+                category: item
+                    .categories()
+                    .first()
+                    .map(|c| c.name().trim().to_string()),
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+fn parse_atom(raw: &str) -> Result<Vec<Entry>, Err> {
+    let feed = atom_syndication::Feed::read_from(raw.as_bytes())?;
+
+    Ok(feed
+        .entries()
+        .iter()
+        .filter_map(|entry| {
+            let text = entry.title().as_str().trim();
+            if text.is_empty() {
+                return None;
+            }
+
+            Some(Entry {
+                text: text.to_string(),
+                author: entry.authors().first().map(|a| a.name().trim().to_string()),
+                category: entry
+                    .categories()
+                    .first()
+                    .map(|c| c.term().trim().to_string()),
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+fn parse_json_lines(raw: &str) -> Result<Vec<Entry>, Err> {
+    let mut entries = vec![];
+    for (number, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = serde_json::from_str::<Entry>(line)
+            .map_err(|e| format!("Line {}: {}", number + 1, e))?;
+        if !entry.text.trim().is_empty() {
+            entries.push(Entry {
+                text: entry.text.trim().to_string(),
+                ..entry
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+fn parse_csv(raw: &str) -> Result<Vec<Entry>, Err> {
+    let mut entries = vec![];
+    for row in csv::Reader::from_reader(raw.as_bytes()).deserialize::<CsvRow>() {
+        let row = row?;
+        if row.text.trim().is_empty() {
+            continue;
+        }
+
+        let custom_fields = match row.fields.as_deref() {
+            Some(fields) => serde_json::from_str(fields)
+                .map_err(|e| format!("Fields of {}: {}", row.text.trim(), e))?,
+            None => vec![],
+        };
+        entries.push(Entry {
+            text: row.text.trim().to_string(),
+            author: row.author,
+            category: row.category,
+            custom_fields,
+            ..Default::default()
+        });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dashes_inside_a_question_are_not_a_credit() {
+        let entry = parse_plain_line("What's better -- cats or dogs -- and why?");
+        assert_eq!(entry.text, "What's better -- cats or dogs -- and why?");
+        assert_eq!(entry.author, None);
+    }
+
+    #[test]
+    fn credit_follows_the_question() {
+        let entry = parse_plain_line("Cats -- or dogs? | Cats | Dogs -- @Ana Lima !pinned");
+        assert_eq!(entry.text, "Cats -- or dogs?");
+        assert_eq!(entry.poll_options, ["Cats", "Dogs"]);
+        assert_eq!(entry.author.as_deref(), Some("Ana Lima"));
+        assert!(entry.pinned);
+
+        let line = entry.to_plain_line();
+        assert_eq!(line, "Cats -- or dogs? | Cats | Dogs -- @Ana Lima !pinned");
+    }
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Questions</title>
+    <link>https://example.com</link>
+    <description>Weekly questions</description>
+    <item>
+      <title> What did you learn this week? </title>
+      <author>ana@example.com (Ana)</author>
+      <category>Learning</category>
+      <category>Weekly</category>
+    </item>
+    <item>
+      <title></title>
+    </item>
+    <item>
+      <title>Best snack for a long drive?</title>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Questions</title>
+  <id>urn:questions</id>
+  <updated>2024-03-01T00:00:00Z</updated>
+  <entry>
+    <title>Which book would you reread?</title>
+    <id>urn:questions:1</id>
+    <updated>2024-03-01T00:00:00Z</updated>
+    <author><name>Ben</name></author>
+    <category term="Books"/>
+  </entry>
+  <entry>
+    <title>Mountains or sea?</title>
+    <id>urn:questions:2</id>
+    <updated>2024-03-01T00:00:00Z</updated>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn rss_items_become_questions() {
+        let entries = SourceFormat::Rss
+            .parse(RSS, &PlainOptions::default())
+            .unwrap();
+
+        assert_eq!(entries.len(), 2, "the empty title is skipped");
+        assert_eq!(entries[0].text, "What did you learn this week?");
+        assert_eq!(entries[0].author.as_deref(), Some("ana@example.com (Ana)"));
+        assert_eq!(entries[0].category.as_deref(), Some("Learning"));
+        assert_eq!(entries[1].text, "Best snack for a long drive?");
+        assert_eq!(entries[1].author, None);
+        assert_eq!(entries[1].category, None);
+    }
+
+    #[test]
+    fn atom_entries_become_questions() {
+        let entries = SourceFormat::Atom
+            .parse(ATOM, &PlainOptions::default())
+            .unwrap();
+
+        let parsed = entries
+            .iter()
+            .map(|e| (e.text.as_str(), e.author.as_deref(), e.category.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parsed,
+            [
+                ("Which book would you reread?", Some("Ben"), Some("Books")),
+                ("Mountains or sea?", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_feeds_are_an_error() {
+        assert!(SourceFormat::Rss
+            .parse("<rss><channel>", &PlainOptions::default())
+            .is_err());
+        assert!(SourceFormat::Atom
+            .parse("not a feed", &PlainOptions::default())
+            .is_err());
+    }
+
+    #[test]
+    fn json_lines_carry_embed_fields() {
+        let raw = r#"{"text": "Best pizza topping?", "category": "Food", "fields": [["Hint", "Not pineapple", true]]}
+
+{"text": " Tea or coffee? ", "poll_options": ["Tea", "Coffee"]}
+{"text": ""}"#;
+        let entries = SourceFormat::JsonLines
+            .parse(raw, &PlainOptions::default())
+            .unwrap();
+
+        assert_eq!(entries.len(), 2, "the empty text is skipped");
+        assert_eq!(entries[0].category.as_deref(), Some("Food"));
+        assert_eq!(
+            entries[0].custom_fields,
+            [("Hint".to_string(), "Not pineapple".to_string(), true)]
+        );
+        assert_eq!(entries[1].text, "Tea or coffee?");
+        assert_eq!(entries[1].poll_options, ["Tea", "Coffee"]);
+        assert!(entries[1].custom_fields.is_empty());
+    }
+
+    #[test]
+    fn csv_rows_carry_embed_fields() {
+        let raw = r#"text,author,category,fields
+"Best pizza topping?",Ana,,"[[""Hint"", ""Not pineapple"", false]]"
+Tea or coffee?,,Drinks,
+"#;
+        let entries = SourceFormat::Csv
+            .parse(raw, &PlainOptions::default())
+            .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].author.as_deref(), Some("Ana"));
+        assert_eq!(entries[0].category, None);
+        assert_eq!(
+            entries[0].custom_fields,
+            [("Hint".to_string(), "Not pineapple".to_string(), false)]
+        );
+        assert_eq!(entries[1].category.as_deref(), Some("Drinks"));
+        assert!(entries[1].custom_fields.is_empty());
+    }
+
+    #[test]
+    fn malformed_fields_are_an_error() {
+        let options = PlainOptions::default();
+        assert!(SourceFormat::JsonLines
+            .parse(r#"{"text": "Why?", "fields": [["Hint"]]}"#, &options)
+            .is_err());
+        assert!(SourceFormat::Csv
+            .parse("text,fields\nWhy?,not json\n", &options)
+            .is_err());
+    }
+
+    #[test]
+    fn comment_lines_are_skipped() {
+        let raw =
+            "# Not a question\nFavourite season?\n  # indented comment\nWhat's #1 on your list?";
+        let options = PlainOptions {
+            comment_char: Some('#'),
+            ..PlainOptions::default()
+        };
+
+        let texts = |entries: Vec<Entry>| entries.into_iter().map(|e| e.text).collect::<Vec<_>>();
+        assert_eq!(
+            texts(parse_plain(raw, &options)),
+            ["Favourite season?", "What's #1 on your list?"]
+        );
+        assert_eq!(texts(parse_plain(raw, &PlainOptions::default())).len(), 4);
+    }
+
+    #[test]
+    fn sections_set_the_category_of_the_lines_below() {
+        let raw = "Before any section?\n## Food\nBest pizza?\nTea or coffee? -- @Ana\n##\nNo category?\n## Travel\nDream trip?";
+        let options = PlainOptions {
+            comment_char: Some('#'),
+            section_categories: true,
+        };
+
+        let parsed = parse_plain(raw, &options)
+            .into_iter()
+            .map(|e| (e.text, e.category))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("Before any section?", None),
+            ("Best pizza?", Some("Food")),
+            ("Tea or coffee?", Some("Food")),
+            ("No category?", None),
+            ("Dream trip?", Some("Travel")),
+        ]
+        .map(|(text, category)| (text.to_string(), category.map(str::to_string)));
+        assert_eq!(parsed, expected);
+    }
+}
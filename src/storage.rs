@@ -0,0 +1,416 @@
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{audit::AuditEntry, bot::Question};
+
+type Err = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Where the bot's state is persisted between ticks and restarts
+#[async_trait]
+pub trait Storage {
+    async fn load(&self) -> Result<State, Err>;
+    async fn save(&self, state: &State) -> Result<(), Err>;
+    /// Where the state is kept, for logging
+    fn location(&self) -> String;
+    /// Fails when the state couldn't be saved, without changing what's stored
+    async fn check_writable(&self) -> Result<(), Err> {
+        Ok(())
+    }
+    /// Waits until no other process is changing the state, which it then can't until the lock
+    /// is dropped
+    async fn lock(&self) -> Result<StateLock, Err> {
+        Ok(StateLock(None))
+    }
+}
+
+/// Keeps other processes from changing the state while it's held
+#[derive(Debug)]
+pub struct StateLock(Option<std::fs::File>);
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        if let Some(file) = &self.0 {
+            let _ = file.unlock();
+        }
+    }
+}
+
+/// Everything persisted to questions.json
+#[derive(Debug, Serialize, Deserialize)]
+pub struct State {
+    version: u32,
+    pub questions: Vec<Question>,
+    #[serde(default)]
+    pub audit_log: Vec<AuditEntry>,
+    /// Shuffled ids of the questions still to be drawn in this cycle
+    #[serde(default)]
+    pub bag: Vec<Uuid>,
+    /// Day the last teaser for an upcoming question was posted on
+    #[serde(default)]
+    pub intro_posted_on: Option<NaiveDate>,
+    /// Question the last teaser was for, which the next post has to use
+    #[serde(default)]
+    pub intro_question: Option<Uuid>,
+    /// When the schedule last posted, manual posts aside
+    #[serde(default)]
+    pub last_scheduled: Option<DateTime<Utc>>,
+    /// Day the last monthly recap was posted on
+    #[serde(default)]
+    pub recap_posted_on: Option<NaiveDate>,
+    /// Day the last weekly summary was posted on
+    #[serde(default)]
+    pub summary_posted_on: Option<NaiveDate>,
+    /// When a question from each category was last posted, for category cooldowns
+    #[serde(default)]
+    pub category_last_posted: HashMap<String, DateTime<Utc>>,
+    /// Normalized text of deleted questions, skipped when the sources still list them
+    #[serde(default)]
+    pub deleted: Vec<String>,
+    /// Questions posted since the state was created, never reset by cycles or pruning
+    #[serde(default)]
+    pub post_count: u64,
+    /// Consecutive days with a post, kept here since pruning and reposts lose the history
+    #[serde(default)]
+    pub streak: usize,
+    /// Day the streak was last extended on
+    #[serde(default)]
+    pub streak_posted_on: Option<NaiveDate>,
+    /// Questions aren't posted while this is set, everything else keeps running
+    #[serde(default)]
+    pub paused: bool,
+    /// Whether the pool running low was reported, cleared once it is refilled
+    #[serde(default)]
+    pub low_watermark_alerted: bool,
+}
+
+impl State {
+    const VERSION: u32 = 2;
+
+    /// Reads the persisted state, upgrading files written by older versions
+    pub fn from_json(contents: &str) -> Result<Self, Err> {
+        if contents.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        let mut value = serde_json::from_str::<Value>(contents)?;
+        // Version 1 was a bare array of questions
+        if value.is_array() {
+            info!("Upgrading questions.json from version 1");
+            value = json!({ "version": 2, "questions": value });
+        }
+
+        match value["version"].as_u64() {
+            Some(version) if version == u64::from(Self::VERSION) => {
+                Ok(serde_json::from_value(value)?)
+            }
+            version => Err(format!("Unsupported questions.json version {:?}", version).into()),
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            version: Self::VERSION,
+            questions: vec![],
+            audit_log: vec![],
+            bag: vec![],
+            intro_posted_on: None,
+            intro_question: None,
+            last_scheduled: None,
+            recap_posted_on: None,
+            summary_posted_on: None,
+            category_last_posted: HashMap::new(),
+            deleted: vec![],
+            post_count: 0,
+            streak: 0,
+            streak_posted_on: None,
+            paused: false,
+            low_watermark_alerted: false,
+        }
+    }
+}
+
+/// Keeps the state in memory only, so nothing is read from or written to disk
+#[derive(Debug, Default)]
+pub struct MemoryStorage(Mutex<String>);
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn load(&self) -> Result<State, Err> {
+        let contents = self.0.lock().map_err(|e| e.to_string())?;
+        State::from_json(&contents)
+    }
+
+    async fn save(&self, state: &State) -> Result<(), Err> {
+        let json = serde_json::to_string(state)?;
+        *self.0.lock().map_err(|e| e.to_string())? = json;
+
+        Ok(())
+    }
+
+    fn location(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+/// Stores the state as JSON in a single file
+#[derive(Debug)]
+pub struct JsonFileStorage {
+    path: PathBuf,
+    /// Indent the JSON, so the file can be diffed when it's kept in version control
+    pub pretty: bool,
+}
+
+impl JsonFileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            pretty: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for JsonFileStorage {
+    async fn load(&self) -> Result<State, Err> {
+        // Create the file on first start
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+            .await?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await?;
+
+        State::from_json(&contents)
+    }
+
+    /// Writes the state to a temporary file first and moves it over the old one, so
+    /// neither a failed serialization nor a crash halfway through loses the last good state
+    async fn save(&self, state: &State) -> Result<(), Err> {
+        let json = match self.pretty {
+            true => serde_json::to_string_pretty(state),
+            false => serde_json::to_string(state),
+        }
+        .map_err(|e| {
+            format!(
+                "Couldn't serialize the state, leaving {} as it was: {}",
+                self.path.display(),
+                e
+            )
+        })?;
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)
+            .await?;
+        file.write_all(json.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp, &self.path).await?;
+
+        Ok(())
+    }
+
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Locks a file next to the questions file, since saving replaces the questions file itself
+    async fn lock(&self) -> Result<StateLock, Err> {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".lock");
+        let file = tokio::task::spawn_blocking(move || {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            file.lock()?;
+            Ok::<_, std::io::Error>(file)
+        })
+        .await??;
+
+        Ok(StateLock(Some(file)))
+    }
+
+    /// Saving writes a temporary file next to the questions file, so that has to work
+    async fn check_writable(&self) -> Result<(), Err> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)
+            .await?;
+        tokio::fs::remove_file(&temp).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("qotd-{}.json", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn json_file_keeps_the_state_between_loads() {
+        let path = temp_path();
+        let mut storage = JsonFileStorage::new(&path);
+        storage.pretty = true;
+
+        let mut state = storage.load().await.unwrap();
+        assert!(state.questions.is_empty(), "a new file starts out empty");
+        let question = Question::new("Saved?".to_string());
+        state.bag.push(question.id());
+        state.questions.push(question);
+        state.paused = true;
+        storage.save(&state).await.unwrap();
+
+        let loaded = storage.load().await.unwrap();
+        assert_eq!(loaded.questions.len(), 1);
+        assert_eq!(loaded.questions[0].text(), "Saved?");
+        assert_eq!(loaded.bag, state.bag);
+        assert!(loaded.paused);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("\n  \"version\": 2"), "{}", contents);
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        assert!(!PathBuf::from(temp).exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_pretty_files_are_indented() {
+        let mut state = State::default();
+        state.questions.push(Question::new("Indented?".to_string()));
+
+        let mut contents = vec![];
+        for pretty in [false, true] {
+            let path = temp_path();
+            let mut storage = JsonFileStorage::new(&path);
+            storage.pretty = pretty;
+            storage.save(&state).await.unwrap();
+            assert_eq!(
+                storage.load().await.unwrap().questions[0].text(),
+                "Indented?"
+            );
+            contents.push(std::fs::read_to_string(&path).unwrap());
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        assert!(!contents[0].contains('\n'), "{}", contents[0]);
+        assert!(
+            contents[1].starts_with("{\n  \"version\": 2,\n"),
+            "{}",
+            contents[1]
+        );
+        assert!(contents[1].contains("\n      \"text\": \"Indented?\""));
+        let parsed = contents
+            .iter()
+            .map(|json| serde_json::from_str::<Value>(json).unwrap())
+            .collect::<Vec<Value>>();
+        assert_eq!(parsed[0], parsed[1]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_leaves_the_last_state() {
+        let path = temp_path();
+        let storage = JsonFileStorage::new(&path);
+        let mut state = storage.load().await.unwrap();
+        state.questions.push(Question::new("Kept?".to_string()));
+        storage.save(&state).await.unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+
+        // A directory where the temporary file goes can't be written
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        std::fs::create_dir(&temp).unwrap();
+        state.questions.push(Question::new("Lost?".to_string()));
+        assert!(storage.save(&state).await.is_err());
+        assert!(storage.check_writable().await.is_err());
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), saved);
+        let loaded = storage.load().await.unwrap();
+        assert_eq!(loaded.questions.len(), 1);
+        assert_eq!(loaded.questions[0].text(), "Kept?");
+        std::fs::remove_dir(&temp).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn memory_keeps_the_state_between_loads() {
+        let storage = MemoryStorage::default();
+        let mut state = storage.load().await.unwrap();
+        state.post_count = 3;
+        storage.save(&state).await.unwrap();
+
+        assert_eq!(storage.load().await.unwrap().post_count, 3);
+    }
+
+    #[tokio::test]
+    async fn json_file_in_a_missing_directory_is_not_writable() {
+        let storage = JsonFileStorage::new(temp_path().join("questions.json"));
+        assert!(storage.check_writable().await.is_err());
+    }
+
+    #[test]
+    fn version_1_files_are_upgraded() {
+        // Written by the first release, before any other field was added
+        let old =
+            r#"[{"id":"0b7c4a8e-3f2d-4c1a-9e5b-6d8f7a2c1b3e","text":"Old?","answered":true}]"#;
+        let state = State::from_json(old).unwrap();
+
+        assert_eq!(state.version, State::VERSION);
+        assert_eq!(state.questions.len(), 1);
+        let question = &state.questions[0];
+        assert_eq!(
+            question.id().to_string(),
+            "0b7c4a8e-3f2d-4c1a-9e5b-6d8f7a2c1b3e"
+        );
+        assert_eq!(question.text(), "Old?");
+        assert!(question.answered());
+        assert_eq!(question.answered_at(), None);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let e = State::from_json(r#"{"version": 3, "questions": []}"#).unwrap_err();
+        assert_eq!(e.to_string(), "Unsupported questions.json version Some(3)");
+    }
+
+    #[tokio::test]
+    async fn a_second_lock_waits_for_the_first() {
+        let path = temp_path();
+        let first = JsonFileStorage::new(&path).lock().await.unwrap();
+
+        let waiting = tokio::spawn(async move { JsonFileStorage::new(path).lock().await });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished(), "the state is still locked");
+
+        drop(first);
+        let second = tokio::time::timeout(std::time::Duration::from_secs(5), waiting).await;
+        assert!(second.unwrap().unwrap().is_ok());
+    }
+}